@@ -0,0 +1,43 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rusty::Lexer;
+
+/// A synthetic program exercising every token class, repeated until it is roughly `bytes` long.
+fn synthetic_source(bytes: usize) -> String {
+    const CHUNK: &str = r#"// a comment that the lexer has to skip over
+class Counter {
+    init(start) { this.count = start; }
+}
+
+fun fib(n) {
+    if (n <= 1) { return n; } else { return fib(n - 1) + fib(n - 2); }
+}
+
+var total = 0;
+for (var i = 0; i < 100; i = i + 1) {
+    total = total + i * 2.5 / (1 + i) - -3;
+    print "iteration " + total;
+}
+while (total >= 10 and !(total == 42) or total != nil) { total = total - 1; }
+"#;
+
+    CHUNK.repeat(bytes / CHUNK.len() + 1)
+}
+
+fn lex(c: &mut Criterion) {
+    let source = synthetic_source(1 << 20);
+    let tokens = Lexer::new(&source).count();
+
+    let mut group = c.benchmark_group("lex");
+    group.throughput(Throughput::Elements(tokens as u64));
+    group.bench_function("synthetic", |b| {
+        b.iter(|| {
+            for token in Lexer::new(black_box(&source)) {
+                black_box(token.expect("synthetic source lexes cleanly"));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, lex);
+criterion_main!(benches);
@@ -0,0 +1,161 @@
+use crate::lex::{LexOptions, Token, TokenKind};
+use crate::Lexer;
+use miette::{Error, LabeledSpan};
+use std::fmt;
+
+/// A lossless syntax tree: every byte of the source, comments and whitespace included, sits in
+/// exactly one token, so printing the tree reproduces the input.
+///
+/// Only brackets give it structure; everything else is a flat run of tokens.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cst<'de> {
+    pub nodes: Vec<Node<'de>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node<'de> {
+    Token(Token<'de>),
+    /// Everything between a `(` or `{` and its closing bracket.
+    Group {
+        open: Token<'de>,
+        children: Vec<Node<'de>>,
+        close: Token<'de>,
+    },
+}
+
+impl<'de> Cst<'de> {
+    pub fn parse(src: &'de str) -> Result<Self, Error> {
+        Self::parse_with_options(src, LexOptions::default())
+    }
+
+    pub fn parse_with_options(src: &'de str, options: LexOptions) -> Result<Self, Error> {
+        let lexer = Lexer::new(src).with_options(LexOptions {
+            trivia: true,
+            ..options
+        });
+
+        // the groups still waiting for their closing bracket, innermost last
+        let mut open: Vec<(Token<'de>, Vec<Node<'de>>)> = Vec::new();
+        let mut nodes = Vec::new();
+        for token in lexer {
+            let token = token?;
+            let closes = match token.kind {
+                TokenKind::LeftParen | TokenKind::LeftBrace => {
+                    open.push((token, std::mem::take(&mut nodes)));
+                    continue;
+                }
+                TokenKind::RightParen => TokenKind::LeftParen,
+                TokenKind::RightBrace => TokenKind::LeftBrace,
+                _ => {
+                    nodes.push(Node::Token(token));
+                    continue;
+                }
+            };
+
+            match open.pop() {
+                Some((opener, outer)) if opener.kind == closes => {
+                    let children = std::mem::replace(&mut nodes, outer);
+                    nodes.push(Node::Group {
+                        open: opener,
+                        children,
+                        close: token,
+                    });
+                }
+                Some((opener, _)) => {
+                    return Err(miette::miette! {
+                        labels = vec![
+                            LabeledSpan::at(opener.span(), "opened here"),
+                            LabeledSpan::at(token.span(), "closed here"),
+                        ],
+                        "Mismatched brackets",
+                    }
+                    .with_source_code(src.to_string()));
+                }
+                None => {
+                    return Err(miette::miette! {
+                        labels = vec![LabeledSpan::at(token.span(), "nothing to close")],
+                        "Unmatched closing bracket",
+                    }
+                    .with_source_code(src.to_string()));
+                }
+            }
+        }
+
+        if let Some((opener, _)) = open.pop() {
+            return Err(miette::miette! {
+                labels = vec![LabeledSpan::at(opener.span(), "never closed")],
+                "Unclosed bracket",
+            }
+            .with_source_code(src.to_string()));
+        }
+
+        Ok(Self { nodes })
+    }
+
+    /// Every token in source order, trivia included.
+    pub fn tokens(&self) -> impl Iterator<Item = &Token<'de>> {
+        let mut tokens = Vec::new();
+        for node in &self.nodes {
+            node.collect_tokens(&mut tokens);
+        }
+        tokens.into_iter()
+    }
+}
+
+impl<'de> Node<'de> {
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a Token<'de>>) {
+        match self {
+            Node::Token(token) => tokens.push(token),
+            Node::Group {
+                open,
+                children,
+                close,
+            } => {
+                tokens.push(open);
+                for child in children {
+                    child.collect_tokens(tokens);
+                }
+                tokens.push(close);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Cst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.tokens() {
+            f.write_str(token.origin)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_byte_for_byte() {
+        let src = "// leading comment\nfun f(a, b) {\n\treturn a + b; // trailing\n}\n\n#line 3\nprint f(1,  2);  \n";
+        let cst = Cst::parse(src).unwrap();
+        assert_eq!(cst.to_string(), src);
+    }
+
+    #[test]
+    fn groups_nest() {
+        let cst = Cst::parse("a (b { c } d) e").unwrap();
+        let groups = cst
+            .nodes
+            .iter()
+            .filter(|node| matches!(node, Node::Group { .. }))
+            .count();
+        assert_eq!(groups, 1);
+        assert_eq!(cst.to_string(), "a (b { c } d) e");
+    }
+
+    #[test]
+    fn unbalanced_brackets_fail() {
+        assert!(Cst::parse("(a").is_err());
+        assert!(Cst::parse("a }").is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{lex::OwnedToken, Lexer};
+use miette::{Error, WrapErr};
+use std::fmt;
+
+/// A position where two token streams disagree on a token's kind or lexeme.
+///
+/// `None` on either side means that stream had already ended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenDiff {
+    pub index: usize,
+    pub left: Option<OwnedToken>,
+    pub right: Option<OwnedToken>,
+}
+
+impl fmt::Display for TokenDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "token #{}: ", self.index)?;
+        match &self.left {
+            Some(token) => write!(f, "{token}")?,
+            None => write!(f, "<end>")?,
+        }
+        write!(f, " != ")?;
+        match &self.right {
+            Some(token) => write!(f, "{token}"),
+            None => write!(f, "<end>"),
+        }
+    }
+}
+
+/// Lexes both sources and reports the first index where their tokens differ, or `None` if they
+/// lex the same.
+///
+/// Only the first difference is reported: after an inserted or dropped token, each later position
+/// would differ too. Offsets are ignored, so reformatting alone produces no diff. Fails with the
+/// first error either source hits while lexing up to that point, since the tokens past it are
+/// unknown.
+pub fn diff_tokens(a: &str, b: &str) -> Result<Option<TokenDiff>, Error> {
+    let mut left = Lexer::new(a);
+    let mut right = Lexer::new(b);
+    let mut index = 0;
+    loop {
+        let l = left.next().transpose().wrap_err("in the first source")?;
+        let r = right.next().transpose().wrap_err("in the second source")?;
+        match (l, r) {
+            (None, None) => return Ok(None),
+            (Some(l), Some(r)) if l.kind == r.kind && l.origin == r.origin => index += 1,
+            (l, r) => {
+                return Ok(Some(TokenDiff {
+                    index,
+                    left: l.map(|t| t.to_owned()),
+                    right: r.map(|t| t.to_owned()),
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_up_to_whitespace() {
+        assert_eq!(diff_tokens("var a = 1;", "var  a=1 ;").unwrap(), None);
+    }
+
+    #[test]
+    fn pinpoints_a_changed_token() {
+        let diff = diff_tokens("print a + b;", "print a - b;")
+            .unwrap()
+            .unwrap();
+        assert_eq!(diff.index, 2);
+        assert_eq!(diff.to_string(), "token #2: PLUS + null != MINUS - null");
+    }
+
+    #[test]
+    fn pinpoints_an_inserted_token() {
+        let diff = diff_tokens("a b c", "a x b c").unwrap().unwrap();
+        assert_eq!(diff.index, 1);
+        assert_eq!(diff.left.unwrap().origin, "b");
+        assert_eq!(diff.right.unwrap().origin, "x");
+    }
+
+    #[test]
+    fn one_stream_ending_early() {
+        let diff = diff_tokens("a b", "a b c").unwrap().unwrap();
+        assert_eq!(diff.left, None);
+        assert_eq!(diff.right.unwrap().origin, "c");
+    }
+
+    #[test]
+    fn lex_errors_are_not_skipped() {
+        let err = diff_tokens("a $ b", "a b").unwrap_err();
+        assert_eq!(err.to_string(), "in the first source");
+        assert!(diff_tokens("a b", "a \"b").is_err());
+        // a difference before the error is still reported
+        assert_eq!(diff_tokens("a b", "x $").unwrap().unwrap().index, 0);
+    }
+}
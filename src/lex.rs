@@ -1,27 +1,134 @@
-use miette::{Diagnostic, Error, LabeledSpan, SourceSpan};
-use std::{borrow::Cow, fmt};
+use crate::Span;
+use miette::{
+    Diagnostic, Error, LabeledSpan, MietteError, MietteSpanContents, SourceCode, SourceSpan,
+    SpanContents,
+};
+use std::{borrow::Cow, collections::VecDeque, fmt, ops::Range, str::FromStr};
 use thiserror::Error;
 
+/// Source text attached to diagnostics, named after the file it came from if that is known.
+#[derive(Debug, Clone)]
+pub struct Source {
+    name: Option<String>,
+    text: String,
+}
+
+impl Source {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl SourceCode for Source {
+    fn read_span<'a>(
+        &'a self,
+        span: &SourceSpan,
+        context_lines_before: usize,
+        context_lines_after: usize,
+    ) -> Result<Box<dyn SpanContents<'a> + 'a>, MietteError> {
+        let contents = self
+            .text
+            .read_span(span, context_lines_before, context_lines_after)?;
+        let Some(name) = &self.name else {
+            return Ok(contents);
+        };
+        Ok(Box::new(MietteSpanContents::new_named(
+            name.clone(),
+            contents.data(),
+            *contents.span(),
+            contents.line(),
+            contents.column(),
+            contents.line_count(),
+        )))
+    }
+}
+
 #[derive(Diagnostic, Debug, Error)]
-#[error("Unexpected EOF")]
-pub struct Eof;
+#[error("Unexpected end of input")]
+pub struct Eof {
+    #[source_code]
+    src: Source,
+
+    #[label("{expected}")]
+    err_span: SourceSpan,
+
+    expected: String,
+}
+
+/// Something suspicious that still lexes; only collected with [`Lexer::with_warnings`].
+#[derive(Diagnostic, Debug, Clone, Error)]
+pub enum LexWarning {
+    #[error("TODO comment")]
+    #[diagnostic(severity(Warning), help("this was left for later"))]
+    TodoComment {
+        #[source_code]
+        src: Source,
+
+        #[label = "still to do"]
+        err_span: SourceSpan,
+    },
+}
 
 #[derive(Diagnostic, Debug, Error)]
-#[error("Unexpected token '{token}'")]
+#[error("Unexpected token '{}'", printable(*.token))]
 pub struct SingleTokenError {
     #[source_code]
-    src: String,
+    src: Source,
 
     pub token: char,
 
     #[label = "this input character"]
     err_span: SourceSpan,
+
+    directive: Option<LineDirective>,
+}
+
+impl Located for SingleTokenError {
+    fn position(&self) -> (&str, SourceSpan, Option<&LineDirective>) {
+        (&self.src.text, self.err_span, self.directive.as_ref())
+    }
 }
 
 impl SingleTokenError {
-    pub fn line(&self) -> usize {
-        let until_unrecongized = &self.src[..=self.err_span.offset()];
-        until_unrecongized.lines().count()
+    /// The unexpected character, with control characters escaped as `\u{XX}`.
+    pub fn printable_token(&self) -> String {
+        printable(self.token)
+    }
+}
+
+/// A run of unexpected characters, reported as one with [`LexOptions::coalesce_bad_chars`].
+#[derive(Diagnostic, Debug, Error)]
+#[error("Unexpected characters '{}'", .chars.chars().map(printable).collect::<String>())]
+pub struct BadCharsError {
+    #[source_code]
+    src: Source,
+
+    pub chars: String,
+
+    #[label = "these input characters"]
+    err_span: SourceSpan,
+
+    directive: Option<LineDirective>,
+}
+
+impl Located for BadCharsError {
+    fn position(&self) -> (&str, SourceSpan, Option<&LineDirective>) {
+        (&self.src.text, self.err_span, self.directive.as_ref())
+    }
+}
+
+impl BadCharsError {
+    /// The unexpected characters, with control characters escaped as `\u{XX}`.
+    pub fn printable_chars(&self) -> String {
+        self.chars.chars().map(printable).collect()
+    }
+}
+
+fn printable(c: char) -> String {
+    if c.is_control() {
+        format!("\\u{{{:02X}}}", u32::from(c))
+    } else {
+        c.to_string()
     }
 }
 
@@ -29,16 +136,134 @@ impl SingleTokenError {
 #[error("Unterminated string")]
 pub struct StringTerminationError {
     #[source_code]
-    src: String,
+    src: Source,
 
     #[label = "this string literal"]
     err_span: SourceSpan,
+
+    directive: Option<LineDirective>,
 }
 
-impl StringTerminationError {
-    pub fn line(&self) -> usize {
-        let until_unrecongized = &self.src[..=self.err_span.offset()];
-        until_unrecongized.lines().count()
+impl Located for StringTerminationError {
+    fn position(&self) -> (&str, SourceSpan, Option<&LineDirective>) {
+        (&self.src.text, self.err_span, self.directive.as_ref())
+    }
+}
+
+#[derive(Diagnostic, Debug, Error)]
+#[error("Leading zero in number literal")]
+#[diagnostic(help("leading zeros are not allowed in strict number mode"))]
+pub struct LeadingZeroError {
+    #[source_code]
+    src: Source,
+
+    #[label = "this numeric literal"]
+    err_span: SourceSpan,
+
+    directive: Option<LineDirective>,
+}
+
+impl Located for LeadingZeroError {
+    fn position(&self) -> (&str, SourceSpan, Option<&LineDirective>) {
+        (&self.src.text, self.err_span, self.directive.as_ref())
+    }
+}
+
+/// A hex, octal or binary literal that doesn't follow its base's syntax, like `0xZ` or `0b102`.
+#[derive(Diagnostic, Debug, Error)]
+#[error("Malformed {base} literal: {why}")]
+pub struct NumberLiteralError {
+    #[source_code]
+    src: Source,
+
+    /// `hex`, `octal` or `binary`.
+    pub base: &'static str,
+
+    pub why: &'static str,
+
+    #[label = "this numeric literal"]
+    err_span: SourceSpan,
+
+    #[help]
+    help: String,
+
+    directive: Option<LineDirective>,
+}
+
+impl Located for NumberLiteralError {
+    fn position(&self) -> (&str, SourceSpan, Option<&LineDirective>) {
+        (&self.src.text, self.err_span, self.directive.as_ref())
+    }
+}
+
+#[derive(Diagnostic, Debug, Error)]
+#[error("Malformed line directive")]
+#[diagnostic(help("expected `#line <number>` or `#line <number> \"<file>\"`"))]
+pub struct LineDirectiveError {
+    #[source_code]
+    src: Source,
+
+    #[label = "this directive"]
+    err_span: SourceSpan,
+
+    directive: Option<LineDirective>,
+}
+
+impl Located for LineDirectiveError {
+    fn position(&self) -> (&str, SourceSpan, Option<&LineDirective>) {
+        (&self.src.text, self.err_span, self.directive.as_ref())
+    }
+}
+
+/// A lexing error that knows where it happened, following any `#line` directive in effect.
+pub trait Located {
+    /// The source text, the span of the error in it, and the `#line` directive in effect.
+    fn position(&self) -> (&str, SourceSpan, Option<&LineDirective>);
+
+    /// The file named by the `#line` directive in effect, if any.
+    fn file(&self) -> Option<&str> {
+        self.position().2?.file.as_deref()
+    }
+
+    /// The line the error starts on, as renumbered by the `#line` directive in effect.
+    fn line(&self) -> usize {
+        let (text, span, directive) = self.position();
+        directive
+            .and_then(|d| d.line_at(text, span.offset()))
+            .unwrap_or_else(|| Span::from(span).line_col(text).0)
+    }
+}
+
+/// A `#line 42 "orig.lox"` directive: the line after it is line 42 of `orig.lox`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineDirective {
+    /// Byte offset of the first line the directive applies to.
+    pub from: usize,
+    pub line: usize,
+    pub file: Option<String>,
+}
+
+impl LineDirective {
+    fn line_at(&self, src: &str, offset: usize) -> Option<usize> {
+        if offset < self.from {
+            return None;
+        }
+        Some(self.line + src[self.from..offset].matches('\n').count())
+    }
+
+    /// Parses what follows the `#`, e.g. `line 42 "orig.lox"`.
+    fn parse(directive: &str) -> Option<(usize, Option<String>)> {
+        let rest = directive.strip_prefix("line")?;
+        if !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let mut parts = rest.trim().splitn(2, char::is_whitespace);
+        let line = parts.next()?.parse().ok()?;
+        let file = match parts.next().map(str::trim) {
+            None => None,
+            Some(file) => Some(file.strip_prefix('"')?.strip_suffix('"')?.to_string()),
+        };
+        Some((line, file))
     }
 }
 
@@ -61,25 +286,44 @@ pub enum TokenKind {
     Plus,
     Semicolon,
     Star,
+    StarStar,
+    Question,
+    QuestionDot,
+    Colon,
+    ColonColon,
     BangEqual,
+    BangEqualEqual,
     EqualEqual,
+    EqualEqualEqual,
     LessEqual,
     GreaterEqual,
     Less,
     Greater,
     Slash,
+    Percent,
+    TildeSlash,
     Bang,
     Equal,
     String,
     Ident,
-    Number(f64),
+    /// `is_int` is set for literals without a fractional part, so `1` but not `1.0`.
+    Number {
+        value: f64,
+        is_int: bool,
+    },
     And,
+    Assert,
+    Break,
     Class,
+    Continue,
+    Elif,
     Else,
     False,
     For,
     Fun,
     If,
+    Import,
+    Mod,
     Nil,
     Or,
     Print,
@@ -87,66 +331,469 @@ pub enum TokenKind {
     Super,
     This,
     True,
+    Use,
     Var,
     While,
+    /// Only produced with [`LexOptions::trivia`].
+    Whitespace,
+    /// A `//` comment or `#line` directive; only produced with [`LexOptions::trivia`].
+    Comment,
+    /// Only produced with [`LexOptions::emit_newlines`].
+    Newline,
+    Eof,
+}
+
+/// A [`TokenKind`] without the payload, so it can be compared exactly, hashed and sorted.
+///
+/// Tags sort in declaration order, which is stable across runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TokenKindTag {
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    Comma,
+    Dot,
+    Minus,
+    Plus,
+    Semicolon,
+    Star,
+    StarStar,
+    Question,
+    QuestionDot,
+    Colon,
+    ColonColon,
+    BangEqual,
+    BangEqualEqual,
+    EqualEqual,
+    EqualEqualEqual,
+    LessEqual,
+    GreaterEqual,
+    Less,
+    Greater,
+    Slash,
+    Percent,
+    TildeSlash,
+    Bang,
+    Equal,
+    String,
+    Ident,
+    Number,
+    And,
+    Assert,
+    Break,
+    Class,
+    Continue,
+    Elif,
+    Else,
+    False,
+    For,
+    Fun,
+    If,
+    Import,
+    Mod,
+    Nil,
+    Or,
+    Print,
+    Return,
+    Super,
+    This,
+    True,
+    Use,
+    Var,
+    While,
+    Whitespace,
+    Comment,
+    Newline,
+    Eof,
+}
+
+impl TokenKind {
+    pub fn tag(&self) -> TokenKindTag {
+        match self {
+            TokenKind::LeftParen => TokenKindTag::LeftParen,
+            TokenKind::RightParen => TokenKindTag::RightParen,
+            TokenKind::LeftBrace => TokenKindTag::LeftBrace,
+            TokenKind::RightBrace => TokenKindTag::RightBrace,
+            TokenKind::Comma => TokenKindTag::Comma,
+            TokenKind::Dot => TokenKindTag::Dot,
+            TokenKind::Minus => TokenKindTag::Minus,
+            TokenKind::Plus => TokenKindTag::Plus,
+            TokenKind::Semicolon => TokenKindTag::Semicolon,
+            TokenKind::Star => TokenKindTag::Star,
+            TokenKind::StarStar => TokenKindTag::StarStar,
+            TokenKind::Question => TokenKindTag::Question,
+            TokenKind::QuestionDot => TokenKindTag::QuestionDot,
+            TokenKind::Colon => TokenKindTag::Colon,
+            TokenKind::ColonColon => TokenKindTag::ColonColon,
+            TokenKind::BangEqual => TokenKindTag::BangEqual,
+            TokenKind::BangEqualEqual => TokenKindTag::BangEqualEqual,
+            TokenKind::EqualEqual => TokenKindTag::EqualEqual,
+            TokenKind::EqualEqualEqual => TokenKindTag::EqualEqualEqual,
+            TokenKind::LessEqual => TokenKindTag::LessEqual,
+            TokenKind::GreaterEqual => TokenKindTag::GreaterEqual,
+            TokenKind::Less => TokenKindTag::Less,
+            TokenKind::Greater => TokenKindTag::Greater,
+            TokenKind::Slash => TokenKindTag::Slash,
+            TokenKind::Percent => TokenKindTag::Percent,
+            TokenKind::TildeSlash => TokenKindTag::TildeSlash,
+            TokenKind::Bang => TokenKindTag::Bang,
+            TokenKind::Equal => TokenKindTag::Equal,
+            TokenKind::String => TokenKindTag::String,
+            TokenKind::Ident => TokenKindTag::Ident,
+            TokenKind::Number { .. } => TokenKindTag::Number,
+            TokenKind::And => TokenKindTag::And,
+            TokenKind::Break => TokenKindTag::Break,
+            TokenKind::Class => TokenKindTag::Class,
+            TokenKind::Continue => TokenKindTag::Continue,
+            TokenKind::Elif => TokenKindTag::Elif,
+            TokenKind::Else => TokenKindTag::Else,
+            TokenKind::False => TokenKindTag::False,
+            TokenKind::For => TokenKindTag::For,
+            TokenKind::Fun => TokenKindTag::Fun,
+            TokenKind::If => TokenKindTag::If,
+            TokenKind::Import => TokenKindTag::Import,
+            TokenKind::Mod => TokenKindTag::Mod,
+            TokenKind::Assert => TokenKindTag::Assert,
+            TokenKind::Nil => TokenKindTag::Nil,
+            TokenKind::Or => TokenKindTag::Or,
+            TokenKind::Print => TokenKindTag::Print,
+            TokenKind::Return => TokenKindTag::Return,
+            TokenKind::Super => TokenKindTag::Super,
+            TokenKind::This => TokenKindTag::This,
+            TokenKind::True => TokenKindTag::True,
+            TokenKind::Use => TokenKindTag::Use,
+            TokenKind::Var => TokenKindTag::Var,
+            TokenKind::While => TokenKindTag::While,
+            TokenKind::Whitespace => TokenKindTag::Whitespace,
+            TokenKind::Newline => TokenKindTag::Newline,
+            TokenKind::Comment => TokenKindTag::Comment,
+            TokenKind::Eof => TokenKindTag::Eof,
+        }
+    }
 }
 
 impl fmt::Display for Token<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let origin = self.origin;
+        let name = self.kind.name();
         match self.kind {
-            TokenKind::LeftParen => write!(f, "LEFT_PAREN {origin} null"),
-            TokenKind::RightParen => write!(f, "RIGHT_PAREN {origin} null"),
-            TokenKind::LeftBrace => write!(f, "LEFT_BRACE {origin} null"),
-            TokenKind::RightBrace => write!(f, "RIGHT_BRACE {origin} null"),
-            TokenKind::Comma => write!(f, "COMMA {origin} null"),
-            TokenKind::Dot => write!(f, "DOT {origin} null"),
-            TokenKind::Minus => write!(f, "MINUS {origin} null"),
-            TokenKind::Plus => write!(f, "PLUS {origin} null"),
-            TokenKind::Semicolon => write!(f, "SEMICOLON {origin} null"),
-            TokenKind::Star => write!(f, "STAR {origin} null"),
-            TokenKind::BangEqual => write!(f, "BANG_EQUAL {origin} null"),
-            TokenKind::EqualEqual => write!(f, "EQUAL_EQUAL {origin} null"),
-            TokenKind::LessEqual => write!(f, "LESS_EQUAL {origin} null"),
-            TokenKind::GreaterEqual => write!(f, "GREATER_EQUAL {origin} null"),
-            TokenKind::Less => write!(f, "LESS {origin} null"),
-            TokenKind::Greater => write!(f, "GREATER {origin} null"),
-            TokenKind::Slash => write!(f, "SLASH {origin} null"),
-            TokenKind::Bang => write!(f, "BANG {origin} null"),
-            TokenKind::Equal => write!(f, "EQUAL {origin} null"),
-            TokenKind::String => write!(f, "STRING {origin} {}", Token::unescape(origin)),
-            TokenKind::Ident => write!(f, "IDENTIFIER {origin} null"),
-            TokenKind::Number(n) => {
-                if n == n.trunc() {
-                    write!(f, "NUMBER {origin} {n}.0")
-                } else {
-                    write!(f, "NUMBER {origin} {n}")
-                }
+            TokenKind::String => write!(f, "{name} {origin} {}", Token::unescape(origin)),
+            // the literal is always printed as a float; `origin` tells `1` and `1.0` apart
+            TokenKind::Number { value: n, .. } => {
+                write!(f, "{name} {origin} ")?;
+                fmt_number(f, n)
             }
-            TokenKind::And => write!(f, "AND {origin} null"),
-            TokenKind::Class => write!(f, "CLASS {origin} null"),
-            TokenKind::Else => write!(f, "ELSE {origin} null"),
-            TokenKind::False => write!(f, "FALSE {origin} null"),
-            TokenKind::For => write!(f, "FOR {origin} null"),
-            TokenKind::Fun => write!(f, "FUN {origin} null"),
-            TokenKind::If => write!(f, "IF {origin} null"),
-            TokenKind::Nil => write!(f, "NIL {origin} null"),
-            TokenKind::Or => write!(f, "OR {origin} null"),
-            TokenKind::Print => write!(f, "PRINT {origin} null"),
-            TokenKind::Return => write!(f, "RETURN {origin} null"),
-            TokenKind::Super => write!(f, "SUPER {origin} null"),
-            TokenKind::This => write!(f, "THIS {origin} null"),
-            TokenKind::True => write!(f, "TRUE {origin} null"),
-            TokenKind::Var => write!(f, "VAR {origin} null"),
-            TokenKind::While => write!(f, "WHILE {origin} null"),
+            TokenKind::Whitespace | TokenKind::Newline => write!(f, "{name} {origin:?} null"),
+            _ => write!(f, "{name} {origin} null"),
+        }
+    }
+}
+
+impl TokenKind {
+    /// The name `tokenize` prints for this kind, like `LEFT_PAREN`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenKind::LeftParen => "LEFT_PAREN",
+            TokenKind::RightParen => "RIGHT_PAREN",
+            TokenKind::LeftBrace => "LEFT_BRACE",
+            TokenKind::RightBrace => "RIGHT_BRACE",
+            TokenKind::Comma => "COMMA",
+            TokenKind::Dot => "DOT",
+            TokenKind::Minus => "MINUS",
+            TokenKind::Plus => "PLUS",
+            TokenKind::Semicolon => "SEMICOLON",
+            TokenKind::Star => "STAR",
+            TokenKind::StarStar => "STAR_STAR",
+            TokenKind::Question => "QUESTION",
+            TokenKind::QuestionDot => "QUESTION_DOT",
+            TokenKind::Colon => "COLON",
+            TokenKind::ColonColon => "COLON_COLON",
+            TokenKind::BangEqual => "BANG_EQUAL",
+            TokenKind::BangEqualEqual => "BANG_EQUAL_EQUAL",
+            TokenKind::EqualEqual => "EQUAL_EQUAL",
+            TokenKind::EqualEqualEqual => "EQUAL_EQUAL_EQUAL",
+            TokenKind::LessEqual => "LESS_EQUAL",
+            TokenKind::GreaterEqual => "GREATER_EQUAL",
+            TokenKind::Less => "LESS",
+            TokenKind::Greater => "GREATER",
+            TokenKind::Slash => "SLASH",
+            TokenKind::Percent => "PERCENT",
+            TokenKind::TildeSlash => "TILDE_SLASH",
+            TokenKind::Bang => "BANG",
+            TokenKind::Equal => "EQUAL",
+            TokenKind::String => "STRING",
+            TokenKind::Ident => "IDENTIFIER",
+            TokenKind::Number { .. } => "NUMBER",
+            TokenKind::And => "AND",
+            TokenKind::Break => "BREAK",
+            TokenKind::Class => "CLASS",
+            TokenKind::Continue => "CONTINUE",
+            TokenKind::Elif => "ELIF",
+            TokenKind::Else => "ELSE",
+            TokenKind::False => "FALSE",
+            TokenKind::For => "FOR",
+            TokenKind::Fun => "FUN",
+            TokenKind::If => "IF",
+            TokenKind::Import => "IMPORT",
+            TokenKind::Mod => "MOD",
+            TokenKind::Assert => "ASSERT",
+            TokenKind::Nil => "NIL",
+            TokenKind::Or => "OR",
+            TokenKind::Print => "PRINT",
+            TokenKind::Return => "RETURN",
+            TokenKind::Super => "SUPER",
+            TokenKind::This => "THIS",
+            TokenKind::True => "TRUE",
+            TokenKind::Use => "USE",
+            TokenKind::Var => "VAR",
+            TokenKind::While => "WHILE",
+            TokenKind::Whitespace => "WHITESPACE",
+            TokenKind::Newline => "NEWLINE",
+            TokenKind::Comment => "COMMENT",
+            TokenKind::Eof => "EOF",
+        }
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown token kind '{0}'")]
+pub struct UnknownTokenKind(pub String);
+
+/// Parses a name from [`TokenKind::name`]; `NUMBER` gives a `NaN` placeholder value.
+impl FromStr for TokenKind {
+    type Err = UnknownTokenKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let kind = match s {
+            "LEFT_PAREN" => TokenKind::LeftParen,
+            "RIGHT_PAREN" => TokenKind::RightParen,
+            "LEFT_BRACE" => TokenKind::LeftBrace,
+            "RIGHT_BRACE" => TokenKind::RightBrace,
+            "COMMA" => TokenKind::Comma,
+            "DOT" => TokenKind::Dot,
+            "MINUS" => TokenKind::Minus,
+            "PLUS" => TokenKind::Plus,
+            "SEMICOLON" => TokenKind::Semicolon,
+            "STAR" => TokenKind::Star,
+            "STAR_STAR" => TokenKind::StarStar,
+            "QUESTION" => TokenKind::Question,
+            "QUESTION_DOT" => TokenKind::QuestionDot,
+            "COLON" => TokenKind::Colon,
+            "COLON_COLON" => TokenKind::ColonColon,
+            "BANG_EQUAL" => TokenKind::BangEqual,
+            "BANG_EQUAL_EQUAL" => TokenKind::BangEqualEqual,
+            "EQUAL_EQUAL" => TokenKind::EqualEqual,
+            "EQUAL_EQUAL_EQUAL" => TokenKind::EqualEqualEqual,
+            "LESS_EQUAL" => TokenKind::LessEqual,
+            "GREATER_EQUAL" => TokenKind::GreaterEqual,
+            "LESS" => TokenKind::Less,
+            "GREATER" => TokenKind::Greater,
+            "SLASH" => TokenKind::Slash,
+            "PERCENT" => TokenKind::Percent,
+            "TILDE_SLASH" => TokenKind::TildeSlash,
+            "BANG" => TokenKind::Bang,
+            "EQUAL" => TokenKind::Equal,
+            "STRING" => TokenKind::String,
+            "IDENTIFIER" => TokenKind::Ident,
+            "NUMBER" => TokenKind::Number {
+                value: f64::NAN,
+                is_int: false,
+            },
+            "AND" => TokenKind::And,
+            "BREAK" => TokenKind::Break,
+            "CLASS" => TokenKind::Class,
+            "CONTINUE" => TokenKind::Continue,
+            "ELIF" => TokenKind::Elif,
+            "ELSE" => TokenKind::Else,
+            "FALSE" => TokenKind::False,
+            "FOR" => TokenKind::For,
+            "FUN" => TokenKind::Fun,
+            "IF" => TokenKind::If,
+            "IMPORT" => TokenKind::Import,
+            "MOD" => TokenKind::Mod,
+            "ASSERT" => TokenKind::Assert,
+            "NIL" => TokenKind::Nil,
+            "OR" => TokenKind::Or,
+            "PRINT" => TokenKind::Print,
+            "RETURN" => TokenKind::Return,
+            "SUPER" => TokenKind::Super,
+            "THIS" => TokenKind::This,
+            "TRUE" => TokenKind::True,
+            "USE" => TokenKind::Use,
+            "VAR" => TokenKind::Var,
+            "WHILE" => TokenKind::While,
+            "WHITESPACE" => TokenKind::Whitespace,
+            "NEWLINE" => TokenKind::Newline,
+            "COMMENT" => TokenKind::Comment,
+            "EOF" => TokenKind::Eof,
+            _ => return Err(UnknownTokenKind(s.to_string())),
+        };
+        Ok(kind)
+    }
+}
+
+impl<'de> Token<'de> {
+    /// The end-of-input marker after the last token of `src`; displays as `EOF  null`.
+    pub fn eof(src: &'de str) -> Self {
+        Token {
+            origin: &src[src.len()..],
+            offset: src.len(),
+            kind: TokenKind::Eof,
+        }
+    }
+
+    /// The text between a string token's quotes, like `hi` for `"hi"`; `None` for other tokens.
+    pub fn string_contents(&self) -> Option<&'de str> {
+        match self.kind {
+            TokenKind::String => Some(&self.origin[1..self.origin.len() - 1]),
+            _ => None,
         }
     }
 }
 
 impl Token<'_> {
+    /// Whether this is the identifier `word`, for words like `get` that the parser treats as
+    /// keywords only in some positions and that stay identifiers everywhere else.
+    pub fn is_contextual_keyword(&self, word: &str) -> bool {
+        self.kind == TokenKind::Ident && self.origin == word
+    }
+
+    /// Where this token's `origin` sits in the source.
+    pub fn span(&self) -> Span {
+        Span::new(self.offset, self.offset + self.origin.len())
+    }
+
+    /// The decoded value of a number token, which `origin` may spell differently, as in `0xff`.
+    pub fn number_value(&self) -> Option<f64> {
+        match self.kind {
+            TokenKind::Number { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The source byte offset of character `idx` of a string token's decoded contents.
+    ///
+    /// Strings have no escape sequences, so the decoded contents are exactly the characters
+    /// between the quotes. Returns `None` for other tokens, or if `idx` is past the end.
+    pub fn decoded_char_byte(&self, idx: usize) -> Option<usize> {
+        let contents = self.string_contents()?;
+        let (byte, _) = contents.char_indices().nth(idx)?;
+        Some(self.offset + 1 + byte)
+    }
+
     pub fn unescape<'de>(s: &'de str) -> Cow<'de, str> {
         Cow::Borrowed(s.trim_matches('"'))
     }
+
+    pub fn to_owned(self) -> OwnedToken {
+        OwnedToken {
+            origin: self.origin.to_string(),
+            offset: self.offset,
+            kind: self.kind,
+        }
+    }
+}
+
+/// A [`Token`] that owns its lexeme, so it can outlive the source it was lexed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedToken {
+    pub origin: String,
+    pub offset: usize,
+    pub kind: TokenKind,
+}
+
+impl OwnedToken {
+    pub fn as_ref(&self) -> Token<'_> {
+        Token {
+            origin: &self.origin,
+            offset: self.offset,
+            kind: self.kind,
+        }
+    }
+}
+
+impl fmt::Display for OwnedToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_ref().fmt(f)
+    }
+}
+
+/// Shorthand for [`Lexer::new`].
+///
+/// ```
+/// let kinds: Vec<_> = rusty::lex("1 + 2").map(|token| token.unwrap().kind.name()).collect();
+/// assert_eq!(kinds, ["NUMBER", "PLUS", "NUMBER"]);
+/// ```
+pub fn lex(src: &str) -> Lexer<'_> {
+    Lexer::new(src)
+}
+
+/// Every token in `src`, or the first error.
+///
+/// ```
+/// let tokens = rusty::lex_all("1 + 2").unwrap();
+/// assert_eq!(tokens[1].origin, "+");
+/// assert!(rusty::lex_all("1 @ 2").is_err());
+/// ```
+pub fn lex_all(src: &str) -> Result<Vec<Token<'_>>, Error> {
+    Lexer::new(src).collect()
+}
+
+/// A hash of the kinds and lexemes of `src`'s tokens, so sources that differ only in whitespace
+/// and comments hash the same.
+///
+/// Uses 64-bit FNV-1a, which gives the same result on every run and platform. An error hashes
+/// as its message, and lexing goes on past it.
+pub fn token_stream_hash(src: &str) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    let mut hash = OFFSET_BASIS;
+    let mut write = |bytes: &[u8]| {
+        for &b in bytes {
+            hash = (hash ^ u64::from(b)).wrapping_mul(PRIME);
+        }
+        // 0xff never occurs in UTF-8, so it keeps `ab` + `c` apart from `a` + `bc`
+        hash = (hash ^ 0xff).wrapping_mul(PRIME);
+    };
+    for token in Lexer::new(src) {
+        match token {
+            Ok(token) => {
+                write(token.kind.name().as_bytes());
+                write(token.origin.as_bytes());
+            }
+            Err(e) => write(e.to_string().as_bytes()),
+        }
+    }
+    hash
+}
+
+/// Switches for dialects that lex differently from plain Lox.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LexOptions {
+    /// Reject multi-digit integers with a leading zero, like `007`.
+    pub strict_numbers: bool,
+    /// Recognize keywords regardless of case, so `WHILE` lexes like `while`.
+    pub case_insensitive_keywords: bool,
+    /// Emit whitespace and comments as tokens rather than skipping them, so that the tokens
+    /// cover every byte of the input. The parser always turns this off.
+    pub trivia: bool,
+    /// Report a run of unexpected characters like `@@@` as one [`BadCharsError`] instead of
+    /// one [`SingleTokenError`] per character.
+    pub coalesce_bad_chars: bool,
+    /// Emit each `\n` as a [`TokenKind::Newline`], for dialects where line breaks matter. Other
+    /// whitespace is still skipped. The parser always turns this off.
+    pub emit_newlines: bool,
+    /// Lex `elif` as [`TokenKind::Elif`], which the parser reads as `else if`, rather than as an
+    /// identifier.
+    pub elif_keyword: bool,
 }
 
 pub struct Lexer<'de> {
@@ -154,8 +801,22 @@ pub struct Lexer<'de> {
     rest: &'de str,
     byte: usize,
     peeked: Option<Result<Token<'de>, miette::Error>>,
+    recorded: Option<Vec<Token<'de>>>,
+    options: LexOptions,
+    line_directive: Option<LineDirective>,
+    source_name: Option<String>,
+    warnings: Option<Vec<LexWarning>>,
+    hook: Option<Hook<'de>>,
+    /// Tokens the hook produced that have not been returned yet.
+    hooked: VecDeque<Token<'de>>,
+    /// Whether the hook has seen the [`TokenKind::Eof`] at the end of the input.
+    hook_done: bool,
+    /// The last token handed out by `next`, not counting a peeked one.
+    last: Option<Token<'de>>,
 }
 
+type Hook<'de> = Box<dyn FnMut(Token<'de>, &mut VecDeque<Token<'de>>) + 'de>;
+
 impl<'de> Lexer<'de> {
     pub fn new(input: &'de str) -> Self {
         Self {
@@ -163,7 +824,150 @@ impl<'de> Lexer<'de> {
             rest: input,
             byte: 0,
             peeked: None,
+            recorded: None,
+            options: LexOptions::default(),
+            line_directive: None,
+            source_name: None,
+            warnings: None,
+            hook: None,
+            hooked: VecDeque::new(),
+            hook_done: false,
+            last: None,
+        }
+    }
+
+    /// Name diagnostics after `name`, usually the path the input was read from.
+    pub fn with_source_name(mut self, name: impl Into<String>) -> Self {
+        self.source_name = Some(name.into());
+        self
+    }
+
+    pub fn with_options(mut self, options: LexOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Pass every token through `hook`, which may rewrite it, drop it by returning `None`, or hold
+    /// it back to return later along with another token.
+    ///
+    /// After the last token the hook sees a [`TokenKind::Eof`], so it can release anything it held
+    /// back; any `Eof` it returns is dropped. Errors bypass the hook.
+    pub fn with_hook<I>(mut self, mut hook: impl FnMut(Token<'de>) -> I + 'de) -> Self
+    where
+        I: IntoIterator<Item = Token<'de>>,
+    {
+        self.hook = Some(Box::new(move |token, out| {
+            out.extend(hook(token).into_iter().filter(|t| t.kind != TokenKind::Eof))
+        }));
+        self
+    }
+
+    /// Keep every token handed out by `next` so far, available from [`Lexer::recorded`]; a token
+    /// that was only peeked at is not recorded until it is taken.
+    pub fn with_recording(mut self) -> Self {
+        self.recorded = Some(Vec::new());
+        self
+    }
+
+    pub fn recorded(&self) -> &[Token<'de>] {
+        self.recorded.as_deref().unwrap_or_default()
+    }
+
+    /// Collect [`LexWarning`]s while lexing, available from [`Lexer::warnings`].
+    pub fn with_warnings(mut self) -> Self {
+        self.warnings = Some(Vec::new());
+        self
+    }
+
+    pub fn warnings(&self) -> &[LexWarning] {
+        self.warnings.as_deref().unwrap_or_default()
+    }
+
+    fn warn(&mut self, warning: LexWarning) {
+        if let Some(warnings) = &mut self.warnings {
+            warnings.push(warning);
+        }
+    }
+
+    /// How many bytes of the input have been lexed, including any token held by [`Lexer::peek`].
+    pub fn offset(&self) -> usize {
+        self.byte
+    }
+
+    /// The input past [`Lexer::offset`], which has not been lexed yet.
+    pub fn remaining(&self) -> &'de str {
+        self.rest
+    }
+
+    /// The input before [`Lexer::offset`], which has been lexed; with [`Lexer::remaining`] it
+    /// makes up the whole input.
+    pub fn consumed(&self) -> &'de str {
+        &self.whole[..self.byte]
+    }
+
+    /// The input, named as given to [`Lexer::with_source_name`], for attaching to diagnostics.
+    pub(crate) fn source(&self) -> Source {
+        Source {
+            name: self.source_name.clone(),
+            text: self.whole.to_string(),
+        }
+    }
+
+    /// The token whose span contains byte offset `byte` of `src`.
+    ///
+    /// Only lexes as far as `byte`. Returns `None` for offsets in whitespace, comments, or input
+    /// that fails to lex.
+    pub fn token_at(src: &'de str, byte: usize) -> Option<Token<'de>> {
+        for token in Lexer::new(src) {
+            let Ok(token) = token else { continue };
+            if token.offset > byte {
+                break;
+            }
+            if byte < token.offset + token.origin.len() {
+                return Some(token);
+            }
+        }
+        None
+    }
+
+    /// The possibly unfinished token that ends at the cursor at byte `byte` of `src`, with its
+    /// text up to the cursor, for completing what is being typed.
+    ///
+    /// Unlike [`Lexer::token_at`], this only looks at the input before the cursor, so `wh` in
+    /// `whale` gives an identifier `wh`, and an unterminated string up to the cursor counts as a
+    /// string. Returns `None` if no token ends at the cursor, such as after whitespace.
+    pub fn token_prefix_at(src: &'de str, byte: usize) -> Option<(TokenKind, &'de str)> {
+        let typed = src.get(..byte)?;
+        let mut last = None;
+        for token in Lexer::new(typed) {
+            match token {
+                Ok(token) => last = Some((token.kind, token.span())),
+                Err(e) => {
+                    // an unterminated string always runs to the end of `typed`
+                    if let Some(e) = e.downcast_ref::<StringTerminationError>() {
+                        last = Some((TokenKind::String, Span::new(e.err_span.offset(), byte)));
+                    }
+                }
+            }
         }
+        let (kind, span) = last?;
+        (span.end == byte).then(|| (kind, span.slice(src)))
+    }
+
+    /// Every `//` comment in `src` with its byte range, including the `//`.
+    ///
+    /// Input that fails to lex is skipped over.
+    pub fn comments(src: &'de str) -> Vec<(Range<usize>, &'de str)> {
+        let options = LexOptions {
+            trivia: true,
+            ..LexOptions::default()
+        };
+        Lexer::new(src)
+            .with_options(options)
+            .filter_map(Result::ok)
+            .filter(|token| token.kind == TokenKind::Comment && token.origin.starts_with("//"))
+            .map(|token| (token.span().into(), token.origin))
+            .collect()
     }
 }
 
@@ -190,10 +994,35 @@ impl<'de> Lexer<'de> {
                 help = format!("Expected {token:?}"),
                 "{unexpected}",
             }
-            .with_source_code(self.whole.to_string())),
+            .with_source_code(self.source())),
             Some(Err(e)) => Err(e),
-            None => Err(Eof.into()),
+            None => Err(self.unexpected_eof(unexpected)),
+        }
+    }
+
+    /// An [`Eof`] error pointing at the end of the input, labelled with what was `expected`.
+    pub(crate) fn unexpected_eof(&self, expected: &str) -> miette::Error {
+        // just past the last token, so the label lands on a line that has content
+        let end = self.whole.trim_end().len();
+        let mut src = self.source();
+        // miette only draws a label at the very end of the input if something follows it
+        src.text.push('\n');
+        Eof {
+            src,
+            err_span: SourceSpan::from(end..end),
+            expected: expected.to_string(),
         }
+        .into()
+    }
+
+    /// The kind of the last token handed out by `next`; peeking at a token does not change it.
+    pub(crate) fn last_kind(&self) -> Option<TokenKind> {
+        self.last.map(|token| token.kind)
+    }
+
+    /// Where the last token handed out by `next` ends; peeking at a token does not change it.
+    pub(crate) fn last_end(&self) -> Option<usize> {
+        self.last.map(|token| token.span().end)
     }
 
     pub fn peek(&mut self) -> Option<&Result<Token<'de>, miette::Error>> {
@@ -201,7 +1030,7 @@ impl<'de> Lexer<'de> {
             return self.peeked.as_ref();
         }
 
-        self.peeked = self.next();
+        self.peeked = self.lex_hooked();
         self.peeked.as_ref()
     }
 }
@@ -211,10 +1040,60 @@ impl<'de> Iterator for Lexer<'de> {
 
     /// Once the iterator returns `Err`, it will only return `None`.
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(next) = self.peeked.take() {
-            return Some(next);
+        let next = match self.peeked.take() {
+            Some(next) => Some(next),
+            None => self.lex_hooked(),
+        };
+        // only here, as a token is handed out, and not when `peek` lexes one ahead
+        if let Some(Ok(token)) = &next {
+            self.last = Some(*token);
+            if let Some(recorded) = &mut self.recorded {
+                recorded.push(*token);
+            }
         }
+        next
+    }
+}
 
+impl<'de> Lexer<'de> {
+    /// The next token after running it through the hook from [`Lexer::with_hook`], if any.
+    fn lex_hooked(&mut self) -> Option<Result<Token<'de>, Error>> {
+        if self.hook.is_none() {
+            return self.lex_next();
+        }
+        loop {
+            if let Some(token) = self.hooked.pop_front() {
+                return Some(Ok(token));
+            }
+            let token = match self.lex_next() {
+                Some(Ok(token)) => token,
+                Some(Err(e)) => return Some(Err(e)),
+                None if self.hook_done => return None,
+                None => {
+                    self.hook_done = true;
+                    Token::eof(self.whole)
+                }
+            };
+            let hook = self.hook.as_mut().expect("checked above");
+            hook(token, &mut self.hooked);
+        }
+    }
+
+    /// Moves `bytes` further into the input.
+    fn advance(&mut self, bytes: usize) {
+        debug_assert!(
+            self.rest.is_char_boundary(bytes),
+            "lexer stopped inside a char at byte {}",
+            self.byte + bytes
+        );
+        // slicing first panics on any `bytes` past the end of `rest`, and `byte + rest.len()` is
+        // always `whole.len()`, which a `str` keeps below `isize::MAX`, so this cannot overflow
+        self.rest = &self.rest[bytes..];
+        self.byte += bytes;
+        debug_assert_eq!(self.byte + self.rest.len(), self.whole.len());
+    }
+
+    fn lex_next(&mut self) -> Option<Result<Token<'de>, Error>> {
         loop {
             // NOTE: this must be in the loop for the indices to match up with c_onwards
             let mut chars = self.rest.chars();
@@ -222,15 +1101,16 @@ impl<'de> Iterator for Lexer<'de> {
             let c_at = self.byte;
             let c_str = &self.rest[..c.len_utf8()];
             let c_onwards = self.rest;
-            self.rest = chars.as_str();
-            self.byte += c.len_utf8();
+            self.advance(c.len_utf8());
 
             enum Started {
+                Directive,
                 Slash,
                 String,
                 Number,
                 Ident,
-                IfEqualElse(TokenKind, TokenKind),
+                IfNextElse(char, TokenKind, TokenKind),
+                Equality(TokenKind, TokenKind, TokenKind),
             }
 
             let just = move |kind: TokenKind| {
@@ -251,21 +1131,72 @@ impl<'de> Iterator for Lexer<'de> {
                 '-' => return just(TokenKind::Minus),
                 '+' => return just(TokenKind::Plus),
                 ';' => return just(TokenKind::Semicolon),
-                '*' => return just(TokenKind::Star),
+                '*' => Started::IfNextElse('*', TokenKind::StarStar, TokenKind::Star),
+                '?' => Started::IfNextElse('.', TokenKind::QuestionDot, TokenKind::Question),
+                ':' => Started::IfNextElse(':', TokenKind::ColonColon, TokenKind::Colon),
+                '%' => return just(TokenKind::Percent),
+                '~' if self.rest.starts_with('/') => {
+                    self.advance(1);
+                    return Some(Ok(Token {
+                        origin: &c_onwards[..c.len_utf8() + 1],
+                        offset: c_at,
+                        kind: TokenKind::TildeSlash,
+                    }));
+                }
                 '/' => Started::Slash,
-                '<' => Started::IfEqualElse(TokenKind::LessEqual, TokenKind::Less),
-                '>' => Started::IfEqualElse(TokenKind::GreaterEqual, TokenKind::Greater),
-                '!' => Started::IfEqualElse(TokenKind::BangEqual, TokenKind::Bang),
-                '=' => Started::IfEqualElse(TokenKind::EqualEqual, TokenKind::Equal),
+                '#' if c_at == 0 || self.whole[..c_at].ends_with('\n') => Started::Directive,
+                '<' => Started::IfNextElse('=', TokenKind::LessEqual, TokenKind::Less),
+                '>' => Started::IfNextElse('=', TokenKind::GreaterEqual, TokenKind::Greater),
+                '!' => Started::Equality(
+                    TokenKind::BangEqualEqual,
+                    TokenKind::BangEqual,
+                    TokenKind::Bang,
+                ),
+                '=' => Started::Equality(
+                    TokenKind::EqualEqualEqual,
+                    TokenKind::EqualEqual,
+                    TokenKind::Equal,
+                ),
                 '"' => Started::String,
                 '0'..='9' => Started::Number,
                 'a'..='z' | 'A'..='Z' | '_' => Started::Ident,
-                c if c.is_whitespace() => continue,
+                '\n' if self.options.emit_newlines => return just(TokenKind::Newline),
+                c if c.is_whitespace() => {
+                    if !self.options.trivia {
+                        continue;
+                    }
+                    let end = c_onwards
+                        .find(|c: char| {
+                            !c.is_whitespace() || (c == '\n' && self.options.emit_newlines)
+                        })
+                        .unwrap_or(c_onwards.len());
+                    self.advance(end - c.len_utf8());
+                    return Some(Ok(Token {
+                        origin: &c_onwards[..end],
+                        offset: c_at,
+                        kind: TokenKind::Whitespace,
+                    }));
+                }
                 c => {
+                    let end = c_onwards
+                        .find(|c: char| c.is_whitespace() || starts_token(c))
+                        .unwrap_or(c_onwards.len());
+                    if self.options.coalesce_bad_chars && end > c.len_utf8() {
+                        self.advance(end - c.len_utf8());
+                        return Some(Err(BadCharsError {
+                            src: self.source(),
+                            chars: c_onwards[..end].to_string(),
+                            err_span: SourceSpan::from(c_at..self.byte),
+                            directive: self.line_directive.clone(),
+                        }
+                        .into()));
+                    }
+
                     return Some(Err(SingleTokenError {
-                        src: self.whole.to_string(),
+                        src: self.source(),
                         token: c,
                         err_span: SourceSpan::from(self.byte - c.len_utf8()..self.byte),
+                        directive: self.line_directive.clone(),
                     }
                     .into()));
                 }
@@ -275,8 +1206,7 @@ impl<'de> Iterator for Lexer<'de> {
                 Started::String => {
                     if let Some(end) = self.rest.find('"') {
                         let literal = &c_onwards[..end + 1 + 1];
-                        self.byte += end + 1;
-                        self.rest = &self.rest[end + 1..];
+                        self.advance(end + 1);
                         Some(Ok(Token {
                             origin: literal,
                             offset: c_at,
@@ -284,24 +1214,70 @@ impl<'de> Iterator for Lexer<'de> {
                         }))
                     } else {
                         let err = StringTerminationError {
-                            src: self.whole.to_string(),
+                            src: self.source(),
                             err_span: SourceSpan::from(self.byte - c.len_utf8()..self.whole.len()),
+                            directive: self.line_directive.clone(),
                         };
 
                         // swallow the remainder of input as being a string
-                        self.byte += self.rest.len();
-                        self.rest = &self.rest[self.rest.len()..];
+                        self.advance(self.rest.len());
 
                         return Some(Err(err.into()));
                     }
                 }
+                Started::Directive => {
+                    let line_end = self.rest.find('\n').unwrap_or(self.rest.len());
+                    let directive = &self.rest[..line_end];
+                    self.advance(line_end);
+
+                    let Some((line, file)) = LineDirective::parse(directive) else {
+                        return Some(Err(LineDirectiveError {
+                            src: self.source(),
+                            err_span: SourceSpan::from(c_at..self.byte),
+                            directive: self.line_directive.clone(),
+                        }
+                        .into()));
+                    };
+
+                    let file = file.or_else(|| self.line_directive.take()?.file);
+                    self.line_directive = Some(LineDirective {
+                        from: self.byte + 1,
+                        line,
+                        file,
+                    });
+                    if !self.options.trivia {
+                        continue;
+                    }
+                    Some(Ok(Token {
+                        origin: &c_onwards[..self.byte - c_at],
+                        offset: c_at,
+                        kind: TokenKind::Comment,
+                    }))
+                }
                 Started::Slash => {
                     if self.rest.starts_with('/') {
                         // this is a comment!
-                        let line_end = self.rest.find('\n').unwrap_or_else(|| self.rest.len());
-                        self.byte += line_end;
-                        self.rest = &self.rest[line_end..];
-                        continue;
+                        let line_end = self.rest.find('\n').unwrap_or(self.rest.len());
+                        self.advance(line_end);
+                        let comment = &c_onwards[..self.byte - c_at];
+
+                        // only copy the source into a warning if warnings are being collected
+                        let todo = comment.find("TODO").filter(|_| self.warnings.is_some());
+                        if let Some(todo) = todo {
+                            self.warn(LexWarning::TodoComment {
+                                src: self.source(),
+                                err_span: SourceSpan::from(c_at + todo..c_at + todo + 4),
+                            });
+                        }
+
+                        if !self.options.trivia {
+                            continue;
+                        }
+                        Some(Ok(Token {
+                            origin: comment,
+                            offset: c_at,
+                            kind: TokenKind::Comment,
+                        }))
                     } else {
                         Some(Ok(Token {
                             origin: c_str,
@@ -313,21 +1289,34 @@ impl<'de> Iterator for Lexer<'de> {
                 Started::Ident => {
                     let first_non_ident = c_onwards
                         .find(|c| !matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '_'))
-                        .unwrap_or_else(|| c_onwards.len());
+                        .unwrap_or(c_onwards.len());
 
                     let literal = &c_onwards[..first_non_ident];
                     let extra_bytes = literal.len() - c.len_utf8();
-                    self.byte += extra_bytes;
-                    self.rest = &self.rest[extra_bytes..];
+                    self.advance(extra_bytes);
 
-                    let kind = match literal {
+                    let lowercased;
+                    let keyword = if self.options.case_insensitive_keywords {
+                        lowercased = literal.to_ascii_lowercase();
+                        &lowercased
+                    } else {
+                        literal
+                    };
+
+                    let kind = match keyword {
                         "and" => TokenKind::And,
+                        "assert" => TokenKind::Assert,
+                        "break" => TokenKind::Break,
                         "class" => TokenKind::Class,
+                        "continue" => TokenKind::Continue,
+                        "elif" if self.options.elif_keyword => TokenKind::Elif,
                         "else" => TokenKind::Else,
                         "false" => TokenKind::False,
                         "for" => TokenKind::For,
                         "fun" => TokenKind::Fun,
                         "if" => TokenKind::If,
+                        "import" => TokenKind::Import,
+                        "mod" => TokenKind::Mod,
                         "nil" => TokenKind::Nil,
                         "or" => TokenKind::Or,
                         "print" => TokenKind::Print,
@@ -335,6 +1324,7 @@ impl<'de> Iterator for Lexer<'de> {
                         "super" => TokenKind::Super,
                         "this" => TokenKind::This,
                         "true" => TokenKind::True,
+                        "use" => TokenKind::Use,
                         "var" => TokenKind::Var,
                         "while" => TokenKind::While,
                         _ => TokenKind::Ident,
@@ -346,10 +1336,45 @@ impl<'de> Iterator for Lexer<'de> {
                         kind,
                     }));
                 }
+                // after a leading 0, only `x`, `o` and `b` switch base; a `.` or another digit
+                // keeps the literal decimal, so `0.5` and `08` lex below like any other number
+                Started::Number
+                    if c == '0' && self.rest.starts_with(['x', 'X', 'o', 'O', 'b', 'B']) =>
+                {
+                    let digits = &self.rest[1..];
+                    let (base, example, (len, parsed)) = match &self.rest[..1] {
+                        "x" | "X" => (
+                            "hex",
+                            "`0xff`, or `0x1.8p3` with a binary exponent",
+                            scan_hex(digits),
+                        ),
+                        "o" | "O" => ("octal", "`0o755`", scan_radix(digits, 8)),
+                        _ => ("binary", "`0b1010`", scan_radix(digits, 2)),
+                    };
+                    self.advance(1 + len);
+                    let literal = &c_onwards[..self.byte - c_at];
+
+                    return Some(match parsed {
+                        Ok((value, is_int)) => Ok(Token {
+                            origin: literal,
+                            offset: c_at,
+                            kind: TokenKind::Number { value, is_int },
+                        }),
+                        Err(why) => Err(NumberLiteralError {
+                            src: self.source(),
+                            base,
+                            why,
+                            err_span: SourceSpan::from(c_at..self.byte),
+                            help: format!("{base} literals look like {example}"),
+                            directive: self.line_directive.clone(),
+                        }
+                        .into()),
+                    });
+                }
                 Started::Number => {
                     let first_non_digit = c_onwards
                         .find(|c| !matches!(c, '.' | '0'..='9'))
-                        .unwrap_or_else(|| c_onwards.len());
+                        .unwrap_or(c_onwards.len());
 
                     let mut literal = &c_onwards[..first_non_digit];
                     let mut dotted = literal.splitn(3, '.');
@@ -357,7 +1382,7 @@ impl<'de> Iterator for Lexer<'de> {
                         (Some(one), Some(two), Some(_)) => {
                             literal = &literal[..one.len() + 1 + two.len()];
                         }
-                        (Some(one), Some(two), None) if two.is_empty() => {
+                        (Some(one), Some(""), None) => {
                             literal = &literal[..one.len()];
                         }
                         _ => {
@@ -365,8 +1390,19 @@ impl<'de> Iterator for Lexer<'de> {
                         }
                     }
                     let extra_bytes = literal.len() - c.len_utf8();
-                    self.byte += extra_bytes;
-                    self.rest = &self.rest[extra_bytes..];
+                    self.advance(extra_bytes);
+
+                    if self.options.strict_numbers
+                        && literal.starts_with('0')
+                        && literal[1..].starts_with(|c: char| c.is_ascii_digit())
+                    {
+                        return Some(Err(LeadingZeroError {
+                            src: self.source(),
+                            err_span: SourceSpan::from(c_at..self.byte),
+                            directive: self.line_directive.clone(),
+                        }
+                        .into()));
+                    }
 
                     let n = match literal.parse() {
                         Ok(n) => n,
@@ -376,24 +1412,40 @@ impl<'de> Iterator for Lexer<'de> {
                                     LabeledSpan::at(self.byte - literal.len()..self.byte, "this numeric literal"),
                                 ],
                                 "{e}",
-                            }.with_source_code(self.whole.to_string())));
+                            }.with_source_code(self.source())));
                         }
                     };
 
                     return Some(Ok(Token {
                         origin: literal,
                         offset: c_at,
-                        kind: TokenKind::Number(n),
+                        kind: TokenKind::Number {
+                            value: n,
+                            is_int: !literal.contains('.'),
+                        },
                     }));
                 }
-                Started::IfEqualElse(yes, no) => {
-                    self.rest = self.rest.trim_start();
-                    let trimmed = c_onwards.len() - self.rest.len() - 1;
-                    self.byte += trimmed;
-                    if self.rest.starts_with('=') {
-                        let span = &c_onwards[..c.len_utf8() + trimmed + 1];
-                        self.rest = &self.rest[1..];
-                        self.byte += 1;
+                Started::Equality(strict, loose, single) => {
+                    // as with IfNextElse, only adjacent '='s combine: `= = =` is three EQUALs
+                    let (kind, extra) = if self.rest.starts_with("==") {
+                        (strict, 2)
+                    } else if self.rest.starts_with('=') {
+                        (loose, 1)
+                    } else {
+                        (single, 0)
+                    };
+                    self.advance(extra);
+                    Some(Ok(Token {
+                        origin: &c_onwards[..c.len_utf8() + extra],
+                        offset: c_at,
+                        kind,
+                    }))
+                }
+                Started::IfNextElse(next, yes, no) => {
+                    // only an immediately adjacent character combines: `= =` is two EQUAL tokens
+                    if self.rest.starts_with(next) {
+                        let span = &c_onwards[..c.len_utf8() + next.len_utf8()];
+                        self.advance(next.len_utf8());
                         Some(Ok(Token {
                             origin: span,
                             offset: c_at,
@@ -411,3 +1463,733 @@ impl<'de> Iterator for Lexer<'de> {
         }
     }
 }
+
+/// Scans a hex literal like `ff` or `1.8p3` from the start of `s`, which follows the `0x`.
+///
+/// Returns how many bytes belong to the literal, even if it turns out to be malformed, along with
+/// its value and whether it is an integer.
+fn scan_hex(s: &str) -> (usize, Result<(f64, bool), &'static str>) {
+    let hex_digits = |s: &str| s.find(|c: char| !c.is_ascii_hexdigit()).unwrap_or(s.len());
+
+    let int_digits = &s[..hex_digits(s)];
+    let mut at = int_digits.len();
+
+    // a `.` only joins the literal when a hex digit or exponent follows, so `0x10.len` is still a
+    // field access; a field starting with a hex digit, like `0x10.foo`, is not
+    let mut frac_digits = "";
+    let fractional = s[at..].starts_with('.')
+        && s[at + 1..].starts_with(|c: char| c.is_ascii_hexdigit() || c == 'p' || c == 'P');
+    if fractional {
+        at += 1;
+        frac_digits = &s[at..at + hex_digits(&s[at..])];
+        at += frac_digits.len();
+    }
+
+    let mut exponent = 0i32;
+    let has_exponent = s[at..].starts_with(['p', 'P']);
+    if has_exponent {
+        at += 1;
+        let sign = usize::from(s[at..].starts_with(['+', '-']));
+        let digits = s[at + sign..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(s.len() - at - sign);
+        let literal = &s[at..at + sign + digits];
+        at += sign + digits;
+        if digits == 0 {
+            return (at, Err("missing exponent digits after `p`"));
+        }
+        exponent = match literal.parse() {
+            Ok(exponent) => exponent,
+            Err(_) => return (at, Err("exponent is too large")),
+        };
+    }
+
+    if int_digits.is_empty() && frac_digits.is_empty() {
+        return (at, Err("missing hex digits"));
+    }
+    if fractional && !has_exponent {
+        return (at, Err("missing `p` exponent after the fraction"));
+    }
+
+    let mut mantissa = 0u64;
+    for c in int_digits.chars().chain(frac_digits.chars()) {
+        let digit = c.to_digit(16).expect("only hex digits were scanned");
+        mantissa = match mantissa.checked_mul(16) {
+            Some(m) => m + u64::from(digit),
+            None => return (at, Err("too many hex digits")),
+        };
+    }
+
+    // each fractional hex digit is four bits below the point
+    let Some(exponent) = i32::try_from(frac_digits.len())
+        .ok()
+        .and_then(|len| exponent.checked_sub(4 * len))
+    else {
+        return (at, Err("exponent is too large"));
+    };
+    let value = mantissa as f64 * 2f64.powi(exponent);
+    (at, Ok((value, !fractional && !has_exponent)))
+}
+
+/// Scans the digits of an octal or binary integer from the start of `s`, which follows the `0o`
+/// or `0b`, the same way as [`scan_hex`].
+fn scan_radix(s: &str, radix: u32) -> (usize, Result<(f64, bool), &'static str>) {
+    // take every decimal digit, so `0b102` is one bad literal rather than `0b10` followed by `2`
+    let len = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let digits = &s[..len];
+    if digits.is_empty() {
+        return (len, Err("missing digits"));
+    }
+    if digits.chars().any(|c| !c.is_digit(radix)) {
+        let why = match radix {
+            8 => "digits must be 0 to 7",
+            _ => "digits must be 0 or 1",
+        };
+        return (len, Err(why));
+    }
+    match u64::from_str_radix(digits, radix) {
+        Ok(n) => (len, Ok((n as f64, true))),
+        Err(_) => (len, Err("too many digits")),
+    }
+}
+
+/// Whether `c` can begin a token, at least in some position; `~` and `#` only sometimes do.
+fn starts_token(c: char) -> bool {
+    matches!(
+        c,
+        '(' | ')' | '{' | '}' | ',' | '.' | '-' | '+' | ';' | ':' | '*' | '?' | '%' | '~' | '/'
+            | '#' | '<' | '>' | '!' | '=' | '"' | '0'..='9' | 'a'..='z' | 'A'..='Z' | '_'
+    )
+}
+
+/// Writes a number the way `tokenize` and the parser print it: integers as `N.0` (so `-0.0` stays
+/// `-0.0`), and a literal too large for an `f64`, like 400 digits, as `Infinity`.
+pub(crate) fn fmt_number(f: &mut fmt::Formatter<'_>, n: f64) -> fmt::Result {
+    if n.is_nan() {
+        write!(f, "NaN")
+    } else if n.is_infinite() {
+        write!(f, "{}Infinity", if n < 0.0 { "-" } else { "" })
+    } else if n == n.trunc() {
+        write!(f, "{n}.0")
+    } else {
+        write!(f, "{n}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_directive_renumbers_errors() {
+        let src = "var a;\n#line 42 \"orig.lox\"\nvar b = @;\n\"open";
+        let errors: Vec<_> = Lexer::new(src).filter_map(Result::err).collect();
+
+        let bad = errors[0].downcast_ref::<SingleTokenError>().unwrap();
+        assert_eq!((bad.line(), bad.file()), (42, Some("orig.lox")));
+        let unterminated = errors[1].downcast_ref::<StringTerminationError>().unwrap();
+        assert_eq!(
+            (unterminated.line(), unterminated.file()),
+            (43, Some("orig.lox"))
+        );
+    }
+
+    #[test]
+    fn errors_before_a_line_directive_keep_their_line() {
+        let src = "@\n#line 42\n@";
+        let errors: Vec<_> = Lexer::new(src).filter_map(Result::err).collect();
+        let lines: Vec<_> = errors
+            .iter()
+            .map(|e| e.downcast_ref::<SingleTokenError>().unwrap())
+            .map(|e| (e.line(), e.file()))
+            .collect();
+        assert_eq!(lines, [(1, None), (42, None)]);
+    }
+
+    #[test]
+    fn builders_combine() {
+        let lexer = Lexer::new("a\nb @")
+            .with_options(LexOptions {
+                case_insensitive_keywords: true,
+                ..LexOptions::default()
+            })
+            .with_source_name("prog.lox")
+            .with_hook(|token: Token<'_>| (token.origin != "a").then_some(token));
+        let results: Vec<_> = lexer.collect();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap().origin, "b");
+
+        let err = results[1].as_ref().unwrap_err();
+        let bad = err.downcast_ref::<SingleTokenError>().unwrap();
+        let contents = bad.src.read_span(&bad.err_span, 0, 0).unwrap();
+        assert_eq!(contents.name(), Some("prog.lox"));
+        assert_eq!(bad.line(), 2);
+    }
+
+    #[test]
+    fn malformed_literals_are_typed() {
+        for (src, base) in [("0xZ", "hex"), ("0o9", "octal"), ("0b102", "binary")] {
+            let err = Lexer::new(src).find_map(Result::err).unwrap();
+            let malformed = err.downcast_ref::<NumberLiteralError>().unwrap();
+            assert_eq!(malformed.base, base, "{src}");
+            assert_eq!(malformed.line(), 1);
+        }
+
+        let err = Lexer::new("print 1;\n#lin 3")
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!(err.downcast_ref::<LineDirectiveError>().unwrap().line(), 2);
+    }
+
+    #[test]
+    fn owned_tokens_round_trip() {
+        let src = "print \"hi\" 1.5;";
+        let owned: Vec<OwnedToken> = {
+            // the owned tokens outlive the source they were lexed from
+            let copy = src.to_string();
+            Lexer::new(&copy)
+                .map(|token| token.unwrap().to_owned())
+                .collect()
+        };
+        let borrowed: Vec<_> = Lexer::new(src).map(Result::unwrap).collect();
+
+        assert_eq!(owned.len(), borrowed.len());
+        for (owned, borrowed) in owned.iter().zip(&borrowed) {
+            let back = owned.as_ref();
+            assert_eq!(back.kind, borrowed.kind);
+            assert_eq!(back.origin, borrowed.origin);
+            assert_eq!(back.offset, borrowed.offset);
+        }
+    }
+
+    fn tags(src: &str) -> Vec<TokenKindTag> {
+        Lexer::new(src)
+            .map(|token| token.unwrap().kind.tag())
+            .collect()
+    }
+
+    #[test]
+    fn star_star() {
+        assert_eq!(tags("**"), [TokenKindTag::StarStar]);
+        assert_eq!(tags("* *"), [TokenKindTag::Star, TokenKindTag::Star]);
+        assert_eq!(tags("***"), [TokenKindTag::StarStar, TokenKindTag::Star]);
+    }
+
+    #[test]
+    fn strict_numbers_reject_leading_zeros() {
+        let strict = LexOptions {
+            strict_numbers: true,
+            ..LexOptions::default()
+        };
+        let lex_strict = |src| {
+            Lexer::new(src)
+                .with_options(strict)
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        let err = lex_strict("007").unwrap_err();
+        assert!(err.downcast_ref::<LeadingZeroError>().is_some());
+        assert!(lex_all("007").is_ok());
+        for src in ["0", "0.5", "42"] {
+            assert!(lex_strict(src).is_ok(), "{src}");
+            assert!(lex_all(src).is_ok(), "{src}");
+        }
+    }
+
+    #[test]
+    fn control_characters_are_escaped() {
+        let err = Lexer::new("a\0b").find_map(Result::err).unwrap();
+        assert_eq!(err.to_string(), "Unexpected token '\\u{00}'");
+        let bad = err.downcast_ref::<SingleTokenError>().unwrap();
+        assert_eq!(bad.printable_token(), "\\u{00}");
+        assert_eq!(
+            Lexer::new("@").find_map(Result::err).unwrap().to_string(),
+            "Unexpected token '@'"
+        );
+    }
+
+    #[test]
+    fn case_insensitive_keywords() {
+        let options = LexOptions {
+            case_insensitive_keywords: true,
+            ..LexOptions::default()
+        };
+        let token = Lexer::new("WHILE")
+            .with_options(options)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(token.kind, TokenKind::While);
+        assert_eq!(token.origin, "WHILE");
+        let ident = Lexer::new("Whilst")
+            .with_options(options)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(ident.kind, TokenKind::Ident);
+
+        assert_eq!(tags("WHILE"), [TokenKindTag::Ident]);
+    }
+
+    #[test]
+    fn modulo_and_floor_division() {
+        assert_eq!(
+            tags("7 % 3 ~/ 2"),
+            [
+                TokenKindTag::Number,
+                TokenKindTag::Percent,
+                TokenKindTag::Number,
+                TokenKindTag::TildeSlash,
+                TokenKindTag::Number
+            ]
+        );
+        // `~` on its own is still unexpected
+        assert!(lex_all("~").is_err());
+    }
+
+    #[test]
+    fn number_display_round_trips() {
+        for src in ["0.0000001", "0.1", "100000000000000000000", "3.14159"] {
+            let token = Lexer::new(src).next().unwrap().unwrap();
+            let shown = token.to_string();
+            let value = shown.rsplit(' ').next().unwrap();
+            let again = Lexer::new(value).next().unwrap().unwrap();
+            assert_eq!(
+                again.number_value(),
+                token.number_value(),
+                "{src} -> {shown}"
+            );
+        }
+    }
+
+    #[test]
+    fn only_adjacent_equals_combine() {
+        assert_eq!(tags("=="), [TokenKindTag::EqualEqual]);
+        assert_eq!(tags("= ="), [TokenKindTag::Equal, TokenKindTag::Equal]);
+        assert_eq!(
+            tags("a == b"),
+            [
+                TokenKindTag::Ident,
+                TokenKindTag::EqualEqual,
+                TokenKindTag::Ident
+            ]
+        );
+        assert_eq!(
+            tags("a = b"),
+            [
+                TokenKindTag::Ident,
+                TokenKindTag::Equal,
+                TokenKindTag::Ident
+            ]
+        );
+    }
+
+    #[test]
+    fn token_at_offsets() {
+        let src = "while (x) print \"a b\";";
+        let at = |byte| Lexer::token_at(src, byte).map(|token| token.origin);
+        assert_eq!(at(2), Some("while"));
+        assert_eq!(at(0), Some("while"));
+        assert_eq!(at(18), Some("\"a b\""));
+        // between tokens, and past the end
+        assert_eq!(at(5), None);
+        assert_eq!(at(src.len()), None);
+    }
+
+    #[test]
+    fn question_dot() {
+        assert_eq!(tags("?."), [TokenKindTag::QuestionDot]);
+        assert_eq!(tags("?"), [TokenKindTag::Question]);
+        assert_eq!(tags("? ."), [TokenKindTag::Question, TokenKindTag::Dot]);
+        // inside a member access, with no spaces around it
+        assert_eq!(
+            tags("a?.b"),
+            [
+                TokenKindTag::Ident,
+                TokenKindTag::QuestionDot,
+                TokenKindTag::Ident
+            ]
+        );
+    }
+
+    #[test]
+    fn identifiers_display_as_identifier() {
+        let token = Lexer::new("foo").next().unwrap().unwrap();
+        assert_eq!(token.to_string(), "IDENTIFIER foo null");
+    }
+
+    #[test]
+    fn strict_equality() {
+        assert_eq!(tags("==="), [TokenKindTag::EqualEqualEqual]);
+        assert_eq!(tags("!=="), [TokenKindTag::BangEqualEqual]);
+        assert_eq!(tags("=="), [TokenKindTag::EqualEqual]);
+        assert_eq!(tags("!="), [TokenKindTag::BangEqual]);
+        assert_eq!(
+            tags("= = ="),
+            [
+                TokenKindTag::Equal,
+                TokenKindTag::Equal,
+                TokenKindTag::Equal
+            ]
+        );
+        assert_eq!(
+            tags("== ="),
+            [TokenKindTag::EqualEqual, TokenKindTag::Equal]
+        );
+    }
+
+    #[test]
+    fn integer_and_float_literals() {
+        let one = Lexer::new("1").next().unwrap().unwrap();
+        let one_point_oh = Lexer::new("1.0").next().unwrap().unwrap();
+        assert_ne!(one.kind, one_point_oh.kind);
+        assert!(matches!(one.kind, TokenKind::Number { is_int: true, .. }));
+        assert!(matches!(
+            one_point_oh.kind,
+            TokenKind::Number { is_int: false, .. }
+        ));
+        assert_eq!(one.number_value(), one_point_oh.number_value());
+        assert_eq!(one.kind.tag(), one_point_oh.kind.tag());
+    }
+
+    #[test]
+    fn comments() {
+        let src = "// header\nvar a = 1; // trailing\nprint \"// not a comment\";\n#line 7\n";
+        let comments = Lexer::comments(src);
+        assert_eq!(comments, [(0..9, "// header"), (21..32, "// trailing")]);
+        assert!(Lexer::comments("print 1;").is_empty());
+    }
+
+    #[test]
+    fn coalesced_bad_characters() {
+        let separate: Vec<_> = Lexer::new("a @@@ b").filter_map(Result::err).collect();
+        assert_eq!(separate.len(), 3);
+        assert!(separate.iter().all(|e| e.is::<SingleTokenError>()));
+
+        let options = LexOptions {
+            coalesce_bad_chars: true,
+            ..LexOptions::default()
+        };
+        let coalesced: Vec<_> = Lexer::new("a @@@ b")
+            .with_options(options)
+            .filter_map(Result::err)
+            .collect();
+        assert_eq!(coalesced.len(), 1);
+        let bad = coalesced[0].downcast_ref::<BadCharsError>().unwrap();
+        assert_eq!(bad.chars, "@@@");
+        assert_eq!(bad.position().1, (2..5).into());
+    }
+
+    #[test]
+    fn numbers_next_to_multibyte_characters() {
+        // none of these may panic on a slice that splits a character
+        for src in ["1é", "é1", "1.é", "0xé", "0b1é", "\"é\"1", "1é.5", "12€"] {
+            let _ = Lexer::new(src).collect::<Vec<_>>();
+        }
+
+        let results: Vec<_> = Lexer::new("1é").collect();
+        assert_eq!(results[0].as_ref().unwrap().origin, "1");
+        let bad = results[1].as_ref().unwrap_err();
+        assert_eq!(bad.downcast_ref::<SingleTokenError>().unwrap().token, 'é');
+    }
+
+    #[test]
+    fn token_kind_names_round_trip() {
+        for kind in [
+            TokenKind::LeftParen,
+            TokenKind::BangEqualEqual,
+            TokenKind::String,
+            TokenKind::Ident,
+            TokenKind::While,
+            TokenKind::Eof,
+        ] {
+            assert_eq!(kind.to_string().parse::<TokenKind>().unwrap(), kind);
+        }
+
+        // a number kind carries its value, so only the tag survives the name
+        let number = TokenKind::Number {
+            value: 1.5,
+            is_int: false,
+        };
+        assert_eq!(number.to_string(), "NUMBER");
+        let parsed: TokenKind = "NUMBER".parse().unwrap();
+        assert_eq!(parsed.tag(), TokenKindTag::Number);
+
+        assert!("LEFT PAREN".parse::<TokenKind>().is_err());
+    }
+
+    #[test]
+    fn token_kind_tags_hash() {
+        use std::collections::HashSet;
+
+        let tags: HashSet<_> = Lexer::new("1 + 2.5 + x")
+            .map(|token| token.unwrap().kind.tag())
+            .collect();
+        assert_eq!(tags.len(), 3);
+        assert!(tags.contains(&TokenKindTag::Number));
+
+        let number = Lexer::new("42").next().unwrap().unwrap();
+        assert_eq!(number.kind.tag(), TokenKindTag::Number);
+    }
+
+    #[test]
+    fn hex_literals() {
+        let value = |src| Lexer::new(src).next().unwrap().unwrap().number_value();
+        assert_eq!(value("0x1.8p3"), Some(12.0));
+        assert_eq!(value("0xff"), Some(255.0));
+        assert_eq!(value("0X10"), Some(16.0));
+        assert_eq!(value("0x1p-2"), Some(0.25));
+
+        for src in ["0x1.p", "0x.p3", "0x", "0x1p"] {
+            let err = Lexer::new(src).find_map(Result::err);
+            assert!(
+                err.is_some_and(|e| e.is::<NumberLiteralError>()),
+                "{src} should be malformed"
+            );
+        }
+    }
+
+    #[test]
+    fn number_value_is_decoded() {
+        let hex = Lexer::new("0xff").next().unwrap().unwrap();
+        assert_eq!(hex.origin, "0xff");
+        assert_eq!(hex.number_value(), Some(255.0));
+
+        let ident = Lexer::new("ff").next().unwrap().unwrap();
+        assert_eq!(ident.number_value(), None);
+    }
+
+    #[test]
+    fn newline_tokens() {
+        let options = LexOptions {
+            emit_newlines: true,
+            ..LexOptions::default()
+        };
+        let tokens: Vec<_> = Lexer::new("a\n\nb \t c\n")
+            .with_options(options)
+            .map(|token| token.unwrap())
+            .map(|token| (token.kind.tag(), token.offset))
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                (TokenKindTag::Ident, 0),
+                (TokenKindTag::Newline, 1),
+                (TokenKindTag::Newline, 2),
+                (TokenKindTag::Ident, 3),
+                (TokenKindTag::Ident, 7),
+                (TokenKindTag::Newline, 8),
+            ]
+        );
+        assert_eq!(tags("a\nb"), [TokenKindTag::Ident, TokenKindTag::Ident]);
+    }
+
+    #[test]
+    fn byte_accounting_on_large_input() {
+        // every `advance` checks the offset invariants in debug builds; this drives them through a
+        // long input with multibyte strings, comments and errors in it
+        let line = "var é = \"ünïcödé\" + 0x1f * 3.5; // çomment\n@";
+        let src = line.repeat(20_000);
+        let mut lexer = Lexer::new(&src);
+        let mut last = 0;
+        while let Some(token) = lexer.next() {
+            if let Ok(token) = token {
+                assert!(token.offset >= last);
+                last = token.offset;
+            }
+            assert_eq!(lexer.consumed().len() + lexer.remaining().len(), src.len());
+        }
+        assert_eq!(lexer.offset(), src.len());
+    }
+
+    #[test]
+    #[ignore = "lexes hundreds of megabytes"]
+    fn byte_accounting_on_huge_input() {
+        let src = "print 1;\n".repeat(50_000_000);
+        assert_eq!(Lexer::new(&src).count(), 150_000_000);
+    }
+
+    #[test]
+    fn todo_comments_warn() {
+        let mut lexer = Lexer::new("print 1; // TODO: more\n// done").with_warnings();
+        assert!(lexer.by_ref().all(|token| token.is_ok()));
+        assert_eq!(lexer.warnings().len(), 1);
+        assert_eq!(
+            miette::Diagnostic::severity(&lexer.warnings()[0]),
+            Some(miette::Severity::Warning)
+        );
+
+        let mut lexer = Lexer::new("// TODO");
+        lexer.by_ref().for_each(drop);
+        assert!(lexer.warnings().is_empty());
+    }
+
+    #[test]
+    fn tag_histogram_sorts_deterministically() {
+        use std::collections::BTreeMap;
+
+        let mut histogram = BTreeMap::new();
+        for token in Lexer::new("var b = a + a; print b;") {
+            *histogram.entry(token.unwrap().kind.tag()).or_insert(0) += 1;
+        }
+        let histogram: Vec<_> = histogram.into_iter().collect();
+        assert_eq!(
+            histogram,
+            [
+                (TokenKindTag::Plus, 1),
+                (TokenKindTag::Semicolon, 2),
+                (TokenKindTag::Equal, 1),
+                (TokenKindTag::Ident, 4),
+                (TokenKindTag::Print, 1),
+                (TokenKindTag::Var, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn leading_zero_disambiguation() {
+        let value = |src| Lexer::new(src).next().unwrap().unwrap().number_value();
+        assert_eq!(value("0.5"), Some(0.5));
+        assert_eq!(value("0"), Some(0.0));
+        assert_eq!(value("0x1"), Some(1.0));
+        assert_eq!(value("0o7"), Some(7.0));
+        assert_eq!(value("0b101"), Some(5.0));
+        assert_eq!(value("08"), Some(8.0));
+
+        let strict = LexOptions {
+            strict_numbers: true,
+            ..LexOptions::default()
+        };
+        let err = Lexer::new("08").with_options(strict).find_map(Result::err);
+        assert!(err.is_some_and(|e| e.is::<LeadingZeroError>()));
+        // a base prefix is not a leading zero
+        assert!(Lexer::new("0o7").with_options(strict).all(|t| t.is_ok()));
+    }
+
+    #[test]
+    fn decoded_char_bytes() {
+        let src = "x = \"abc\";";
+        let string = Lexer::new(src).nth(2).unwrap().unwrap();
+        assert_eq!(string.decoded_char_byte(0), Some(5));
+        assert_eq!(string.decoded_char_byte(2), Some(7));
+        assert_eq!(string.decoded_char_byte(3), None);
+
+        // a multibyte character shifts the bytes of everything after it
+        let string = Lexer::new("\"aéb\"").next().unwrap().unwrap();
+        assert_eq!(string.decoded_char_byte(1), Some(2));
+        assert_eq!(string.decoded_char_byte(2), Some(4));
+
+        let ident = Lexer::new("abc").next().unwrap().unwrap();
+        assert_eq!(ident.decoded_char_byte(0), None);
+    }
+
+    #[test]
+    fn special_numbers_display() {
+        let huge = "9".repeat(400);
+        let token = Lexer::new(&huge).next().unwrap().unwrap();
+        assert_eq!(token.to_string(), format!("NUMBER {huge} Infinity"));
+
+        struct Show(f64);
+        impl fmt::Display for Show {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt_number(f, self.0)
+            }
+        }
+        assert_eq!(Show(f64::NAN).to_string(), "NaN");
+        assert_eq!(Show(f64::NEG_INFINITY).to_string(), "-Infinity");
+        assert_eq!(Show(-0.0).to_string(), "-0.0");
+        assert_eq!(Show(2.5).to_string(), "2.5");
+    }
+
+    #[test]
+    fn hook_merges_adjacent_strings() {
+        let src = "print \"a\" \"b\";";
+        let mut held: Option<Token> = None;
+        let merged: Vec<_> = Lexer::new(src)
+            .with_hook(move |token| match (held.take(), token.kind) {
+                (Some(first), TokenKind::String) => {
+                    held = Some(Token {
+                        origin: &src[first.offset..token.offset + token.origin.len()],
+                        offset: first.offset,
+                        kind: TokenKind::String,
+                    });
+                    vec![]
+                }
+                (None, TokenKind::String) => {
+                    held = Some(token);
+                    vec![]
+                }
+                (first, _) => first.into_iter().chain([token]).collect(),
+            })
+            .map(|t| t.unwrap())
+            .collect();
+        let merged: Vec<_> = merged.iter().map(|t| (t.kind.name(), t.origin)).collect();
+        assert_eq!(
+            merged,
+            [
+                ("PRINT", "print"),
+                ("STRING", "\"a\" \"b\""),
+                ("SEMICOLON", ";"),
+            ]
+        );
+    }
+
+    #[test]
+    fn token_stream_hash_ignores_layout() {
+        let hash = token_stream_hash("var a = 1; // one\nprint a;");
+        assert_eq!(hash, token_stream_hash("var a=1;\n\n  print   a ;"));
+        assert_ne!(hash, token_stream_hash("var a = 2; print a;"));
+        assert_ne!(hash, token_stream_hash("var a = 1.0; print a;"));
+    }
+
+    #[test]
+    fn string_contents() {
+        let tokens = lex_all("\"hi\" \"\" hi").unwrap();
+        assert_eq!(tokens[0].string_contents(), Some("hi"));
+        assert_eq!(tokens[1].string_contents(), Some(""));
+        assert_eq!(tokens[2].string_contents(), None);
+    }
+
+    #[test]
+    fn token_prefix_at_cursor() {
+        let src = "while (x) print 123;";
+        assert_eq!(
+            Lexer::token_prefix_at(src, 2),
+            Some((TokenKind::Ident, "wh"))
+        );
+        assert_eq!(
+            Lexer::token_prefix_at(src, 5),
+            Some((TokenKind::While, "while"))
+        );
+        let (kind, typed) = Lexer::token_prefix_at(src, 18).unwrap();
+        assert!(matches!(kind, TokenKind::Number { value, .. } if value == 12.0));
+        assert_eq!(typed, "12");
+        assert_eq!(Lexer::token_prefix_at(src, 6), None);
+        assert_eq!(
+            Lexer::token_prefix_at("print \"ab", 8),
+            Some((TokenKind::String, "\"a"))
+        );
+    }
+
+    #[test]
+    fn remaining_and_consumed_partition_the_input() {
+        let src = "var a = 1;";
+        let mut lexer = Lexer::new(src);
+        assert_eq!((lexer.consumed(), lexer.remaining()), ("", src));
+        lexer.next();
+        lexer.next();
+        assert_eq!(lexer.consumed(), "var a");
+        assert_eq!(lexer.remaining(), " = 1;");
+        assert_eq!(lexer.offset(), 5);
+    }
+
+    #[test]
+    fn peeking_leaves_last_kind() {
+        let mut lexer = Lexer::new("a;");
+        lexer.next();
+        lexer.peek();
+        assert_eq!(lexer.last_kind(), Some(TokenKind::Ident));
+        lexer.next();
+        assert_eq!(lexer.last_kind(), Some(TokenKind::Semicolon));
+    }
+}
@@ -1,5 +1,18 @@
 pub mod lex;
-pub use lex::Lexer;
+pub use lex::{lex, lex_all, token_stream_hash, Lexer};
 
 pub mod parse;
-pub use parse::Parser;
+pub use parse::{Parser, Program};
+
+pub mod diff;
+pub use diff::diff_tokens;
+
+pub mod span;
+pub use span::Span;
+
+pub mod cst;
+pub use cst::Cst;
+
+pub mod prelude;
+
+pub mod wat;
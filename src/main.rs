@@ -1,7 +1,9 @@
-use clap::{Parser, Subcommand};
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum};
 use miette::{IntoDiagnostic, WrapErr};
+use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 use rusty as imp;
 
@@ -9,79 +11,764 @@ use rusty as imp;
 #[command(version, about, long_about = None)]
 struct Args {
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
+
+    /// Run PROGRAM, given as text, instead of a subcommand; imports resolve from the current
+    /// directory
+    #[arg(short = 'e', long = "eval", value_name = "PROGRAM")]
+    eval: Option<String>,
+
+    /// Reject integer literals with leading zeros, like `007`
+    #[arg(long, global = true)]
+    strict_numbers: bool,
+
+    /// Recognize keywords regardless of case, like `WHILE`
+    #[arg(long, global = true)]
+    case_insensitive_keywords: bool,
+
+    /// Report a run of unexpected characters like `@@@` as a single error
+    #[arg(long, global = true)]
+    coalesce_bad_chars: bool,
+
+    /// Accept `elif` as a keyword meaning `else if`
+    #[arg(long, global = true)]
+    elif_keyword: bool,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    Tokenize { filename: PathBuf },
-    Parse { filename: PathBuf },
-    Run { filename: PathBuf },
+    Tokenize {
+        /// The files to tokenize, each after a `==> name <==` header if there are several
+        #[arg(required = true)]
+        filenames: Vec<PathBuf>,
+
+        /// Report token count, bytes, elapsed time and peak allocation to stderr
+        #[arg(long)]
+        stats: bool,
+
+        /// Only print tokens of this kind, like `IDENTIFIER`; can be repeated
+        #[arg(long, value_name = "KIND")]
+        only: Vec<imp::lex::TokenKind>,
+
+        /// Print a table with aligned kind, lexeme, line and span columns
+        #[arg(long)]
+        pretty: bool,
+
+        /// Print the kinds of the tokens on each line after its number, like `line 1: VAR ...`
+        #[arg(long, conflicts_with = "pretty")]
+        group_by_line: bool,
+
+        /// Leave out the final `EOF  null` token
+        #[arg(long)]
+        no_eof: bool,
+
+        /// Also write a JSON source map to FILE, listing `[start, end, line, column]` for each
+        /// printed token of each file
+        #[arg(long, value_name = "FILE")]
+        source_map: Option<PathBuf>,
+    },
+    Parse {
+        filename: PathBuf,
+
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+
+        /// Only report syntax errors in the expression, without printing its tree
+        #[arg(long)]
+        check_only: bool,
+
+        /// Give up on expressions nested more than this many levels deep
+        #[arg(long)]
+        max_depth: Option<usize>,
+    },
+    Run {
+        filename: PathBuf,
+
+        /// Run again whenever the file changes, until interrupted with Ctrl-C
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Compile the program to another language; experimental
+    Compile {
+        filename: PathBuf,
+
+        #[arg(long, value_enum)]
+        target: Target,
+    },
+    /// Report lexing and parsing errors without printing any output
+    Check { filename: PathBuf },
+    /// Print every step the lexer takes: where it is, what is left, and what it produced
+    Debug { filename: PathBuf },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+enum Format {
+    /// S-expressions, e.g. `(+ 1.0 2.0)`
+    #[default]
+    Sexpr,
+    /// The syntax tree as JSON, with the `start` and `end` byte offsets of every literal and name
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Target {
+    /// WebAssembly text, for programs that only print arithmetic on numbers
+    Wat,
 }
 
 fn main() -> miette::Result<()> {
     let args = Args::parse();
-    match args.command {
-        Commands::Tokenize { filename } => {
+    let options = imp::lex::LexOptions {
+        strict_numbers: args.strict_numbers,
+        case_insensitive_keywords: args.case_insensitive_keywords,
+        coalesce_bad_chars: args.coalesce_bad_chars,
+        elif_keyword: args.elif_keyword,
+        ..Default::default()
+    };
+    let command = match (args.eval, args.command) {
+        (None, Some(command)) => command,
+        (Some(program), None) => return exit_if_malformed(run_inline(program, options)),
+        (Some(_), Some(_)) => Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "-e can't be used with a subcommand",
+            )
+            .exit(),
+        (None, None) => Args::command()
+            .error(ErrorKind::MissingSubcommand, "expected a subcommand or -e")
+            .exit(),
+    };
+    match command {
+        Commands::Tokenize {
+            filenames,
+            stats,
+            only,
+            pretty,
+            group_by_line,
+            no_eof,
+            source_map,
+        } => {
             let mut any_cc_err = false;
 
-            let file_contents = fs::read_to_string(&filename)
-                .into_diagnostic()
-                .wrap_err_with(|| format!("reading '{}' failed", filename.display()))?;
+            let only: HashSet<_> = only.iter().map(|kind| kind.tag()).collect();
 
-            for token in imp::Lexer::new(&file_contents) {
-                let token = match token {
-                    Ok(t) => t,
-                    Err(e) => {
-                        eprintln!("{e:?}");
-                        if let Some(unrecognized) = e.downcast_ref::<imp::lex::SingleTokenError>() {
-                            any_cc_err = true;
-                            eprintln!(
-                                "[line {}] Error: Unexpected character: {}",
-                                unrecognized.line(),
-                                unrecognized.token
-                            );
-                        } else if let Some(unterminated) =
-                            e.downcast_ref::<imp::lex::StringTerminationError>()
-                        {
-                            any_cc_err = true;
-                            eprintln!("[line {}] Error: Unterminated string.", unterminated.line(),);
+            let shown = |kind: imp::lex::TokenKind| only.is_empty() || only.contains(&kind.tag());
+
+            let mut maps = Vec::new();
+            for filename in &filenames {
+                if filenames.len() > 1 {
+                    println!("==> {} <==", filename.display());
+                }
+                let file_contents = fs::read_to_string(filename)
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("reading '{}' failed", filename.display()))?;
+
+                // with --pretty, tokens are held back until the column widths are known, and with
+                // --group-by-line until each line is complete
+                let hold = pretty || group_by_line;
+                let mut held = Vec::new();
+                // the span of every printed token, for --source-map
+                let mut spans = Vec::new();
+
+                let start = Instant::now();
+                let mut token_count = 0;
+                let lexer = imp::Lexer::new(&file_contents)
+                    .with_options(options)
+                    .with_source_name(filename.display().to_string());
+                for token in lexer {
+                    let token = match token {
+                        Ok(t) => t,
+                        Err(e) => {
+                            eprintln!("{e:?}");
+                            if let Some(unrecognized) =
+                                e.downcast_ref::<imp::lex::SingleTokenError>()
+                            {
+                                any_cc_err = true;
+                                eprintln!(
+                                    "{} Error: Unexpected character: {}",
+                                    error_location(unrecognized),
+                                    unrecognized.printable_token()
+                                );
+                            } else if let Some(unrecognized) =
+                                e.downcast_ref::<imp::lex::BadCharsError>()
+                            {
+                                any_cc_err = true;
+                                eprintln!(
+                                    "{} Error: Unexpected characters: {}",
+                                    error_location(unrecognized),
+                                    unrecognized.printable_chars()
+                                );
+                            } else if let Some(unterminated) =
+                                e.downcast_ref::<imp::lex::StringTerminationError>()
+                            {
+                                any_cc_err = true;
+                                eprintln!(
+                                    "{} Error: Unterminated string.",
+                                    error_location(unterminated),
+                                );
+                            } else if let Some(leading_zero) =
+                                e.downcast_ref::<imp::lex::LeadingZeroError>()
+                            {
+                                any_cc_err = true;
+                                eprintln!(
+                                    "{} Error: Leading zero in number.",
+                                    error_location(leading_zero)
+                                );
+                            } else if let Some(malformed) =
+                                e.downcast_ref::<imp::lex::NumberLiteralError>()
+                            {
+                                any_cc_err = true;
+                                eprintln!(
+                                    "{} Error: Malformed {} literal.",
+                                    error_location(malformed),
+                                    malformed.base
+                                );
+                            } else if let Some(malformed) =
+                                e.downcast_ref::<imp::lex::LineDirectiveError>()
+                            {
+                                any_cc_err = true;
+                                eprintln!(
+                                    "{} Error: Malformed line directive.",
+                                    error_location(malformed)
+                                );
+                            }
+                            continue;
                         }
+                    };
+                    token_count += 1;
+                    if !shown(token.kind) {
                         continue;
                     }
-                };
-                println!("{token}");
+                    if source_map.is_some() {
+                        spans.push(token.span());
+                    }
+                    if hold {
+                        held.push(token);
+                    } else {
+                        println!("{token}");
+                    }
+                }
+                let eof = imp::lex::Token::eof(&file_contents);
+                if source_map.is_some() && !no_eof && shown(eof.kind) {
+                    spans.push(eof.span());
+                }
+                if hold {
+                    if !no_eof && shown(eof.kind) {
+                        held.push(eof);
+                    }
+                    if pretty {
+                        print_token_table(&file_contents, &held);
+                    } else {
+                        print_tokens_by_line(&file_contents, &held);
+                    }
+                } else if !no_eof && shown(eof.kind) {
+                    println!("{eof}");
+                }
+
+                if stats {
+                    eprintln!("tokens: {token_count}");
+                    eprintln!("bytes: {}", file_contents.len());
+                    eprintln!("elapsed: {:?}", start.elapsed());
+                    match alloc_stats::peak() {
+                        Some(peak) => eprintln!("peak allocation: {peak} bytes"),
+                        None => {
+                            eprintln!(
+                                "peak allocation: unavailable without the alloc-stats feature"
+                            )
+                        }
+                    }
+                }
+
+                if source_map.is_some() {
+                    let mut line_cols = imp::span::LineCols::new(&file_contents);
+                    let tokens: Vec<_> = spans
+                        .iter()
+                        .map(|span| {
+                            let (line, col) = line_cols.line_col(span.start);
+                            [span.start, span.end, line, col]
+                        })
+                        .collect();
+                    maps.push(serde_json::json!({
+                        "file": filename.display().to_string(),
+                        "tokens": tokens,
+                    }));
+                }
+            }
+
+            if let Some(path) = source_map {
+                fs::write(&path, serde_json::Value::from(maps).to_string())
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("writing '{}' failed", path.display()))?;
             }
-            println!("EOF  null");
 
             if any_cc_err {
-                std::process::exit(65);
+                std::process::exit(exit_codes::DATA_ERR);
             }
         }
-        Commands::Parse { filename } => {
+        Commands::Parse {
+            filename,
+            format,
+            check_only,
+            max_depth,
+        } => {
             let file_contents = fs::read_to_string(&filename)
                 .into_diagnostic()
                 .wrap_err_with(|| format!("reading '{}' failed", filename.display()))?;
 
-            let parser = imp::Parser::new(&file_contents);
+            let mut parser = imp::Parser::new(&file_contents)
+                .with_options(options)
+                .with_source_name(filename.display().to_string());
+            if let Some(max_depth) = max_depth {
+                parser = parser.with_max_depth(max_depth);
+            }
+
             match parser.parse_expression() {
-                Ok(tt) => println!("{tt}"),
+                Ok(_) if check_only => {}
+                Ok(tt) => match format {
+                    Format::Sexpr => println!("{tt}"),
+                    Format::Json => println!(
+                        "{}",
+                        serde_json::to_string(&tt).expect("token trees always serialize")
+                    ),
+                },
                 Err(e) => {
                     // TODO: match error line format
                     eprintln!("{e:?}");
-                    std::process::exit(65);
+                    std::process::exit(exit_codes::DATA_ERR);
                 }
             }
         }
-        Commands::Run { filename } => {
+        Commands::Run { filename, watch } => {
+            let root = canonical(&filename)?;
+            if watch {
+                watch::watch(&root, || {
+                    // keep watching through errors; the next save may well fix them
+                    if let Err(RunError::Malformed(e) | RunError::Other(e)) = run(&root, options) {
+                        eprintln!("{e:?}");
+                    }
+                })?;
+            } else {
+                exit_if_malformed(run(&root, options))?;
+            }
+        }
+        Commands::Compile { filename, target } => {
             let file_contents = fs::read_to_string(&filename)
                 .into_diagnostic()
                 .wrap_err_with(|| format!("reading '{}' failed", filename.display()))?;
 
-            let parser = imp::Parser::new(&file_contents);
-            println!("{}", parser.parse().unwrap());
+            let statements = match imp::Parser::new(&file_contents)
+                .with_options(options)
+                .with_source_name(filename.display().to_string())
+                .parse()
+            {
+                Ok(statements) => statements,
+                Err(e) => {
+                    eprintln!("{e:?}");
+                    std::process::exit(exit_codes::DATA_ERR);
+                }
+            };
+            match target {
+                Target::Wat => print!("{}", imp::wat::emit(&statements)?),
+            }
         }
+        Commands::Check { filename } => {
+            let file_contents = fs::read_to_string(&filename)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("reading '{}' failed", filename.display()))?;
+
+            // the lexer keeps going after an error, so this reports every bad token at once
+            let mut any_err = false;
+            let mut lexer = imp::Lexer::new(&file_contents)
+                .with_options(options)
+                .with_source_name(filename.display().to_string())
+                .with_warnings();
+            for token in lexer.by_ref() {
+                if let Err(e) = token {
+                    any_err = true;
+                    eprintln!("{e:?}");
+                }
+            }
+            // warnings are printed, but don't fail the check
+            for warning in lexer.warnings() {
+                eprintln!("{:?}", miette::Report::new(warning.clone()));
+            }
+
+            // the parser would only repeat the lexer's errors
+            if !any_err {
+                let mut parser = imp::Parser::new(&file_contents)
+                    .with_options(options)
+                    .with_source_name(filename.display().to_string())
+                    .with_warnings();
+                // recovers after each bad statement, so this reports every one of them
+                for e in parser.validate() {
+                    any_err = true;
+                    eprintln!("{e:?}");
+                }
+                for warning in parser.warnings() {
+                    eprintln!("{:?}", miette::Report::new(warning.clone()));
+                }
+            }
+
+            if any_err {
+                std::process::exit(exit_codes::DATA_ERR);
+            }
+        }
+        Commands::Debug { filename } => {
+            let file_contents = fs::read_to_string(&filename)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("reading '{}' failed", filename.display()))?;
+
+            let mut lexer = imp::Lexer::new(&file_contents).with_options(options);
+            loop {
+                let byte = lexer.offset();
+                let rest: String = lexer.remaining().chars().take(16).collect();
+                let rest = format!("{rest:?}");
+                let step = match lexer.next() {
+                    Some(Ok(token)) => token.to_string(),
+                    Some(Err(e)) => format!("error: {e}"),
+                    None => break,
+                };
+                println!("{byte:>6} {rest:<40} -> {step}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The `[line 42]` that starts a tokenize error line, or `[line 42 of orig.lox]` when a `#line`
+/// directive names the file.
+fn error_location(e: &impl imp::lex::Located) -> String {
+    match e.file() {
+        Some(file) => format!("[line {} of {file}]", e.line()),
+        None => format!("[line {}]", e.line()),
     }
+}
 
+/// Process exit codes, following BSD's `sysexits.h`.
+mod exit_codes {
+    /// The input was malformed: a lexing or parsing error, or an import cycle.
+    pub const DATA_ERR: i32 = 65;
+}
+
+#[cfg(feature = "alloc-stats")]
+#[global_allocator]
+static ALLOC: alloc_stats::Counting = alloc_stats::Counting;
+
+#[cfg(feature = "alloc-stats")]
+mod alloc_stats {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CURRENT: AtomicUsize = AtomicUsize::new(0);
+    static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+    /// The system allocator, keeping track of the most bytes ever allocated at once.
+    pub struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            // SAFETY: forwarded unchanged from our caller
+            let ptr = unsafe { System.alloc(layout) };
+            if !ptr.is_null() {
+                let now = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+                PEAK.fetch_max(now, Ordering::Relaxed);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+            // SAFETY: forwarded unchanged from our caller
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    pub fn peak() -> Option<usize> {
+        Some(PEAK.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(not(feature = "alloc-stats"))]
+mod alloc_stats {
+    pub fn peak() -> Option<usize> {
+        None
+    }
+}
+
+#[cfg(feature = "watch")]
+mod watch {
+    use miette::IntoDiagnostic;
+    use notify::{RecursiveMode, Watcher};
+    use std::path::Path;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    /// Editors often write a file in several steps; wait this long for them all to land.
+    const DEBOUNCE: Duration = Duration::from_millis(100);
+
+    /// Calls `run` now and after every change to `path`, forever.
+    pub fn watch(path: &Path, mut run: impl FnMut()) -> miette::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).into_diagnostic()?;
+        // watch the directory, since editors often replace the file rather than write to it
+        let dir = path.parent().unwrap_or(Path::new("."));
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .into_diagnostic()?;
+
+        run();
+        loop {
+            let event = rx.recv().into_diagnostic()?.into_diagnostic()?;
+            if !touches(&event, path) {
+                continue;
+            }
+            while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+                event.into_diagnostic()?;
+            }
+            run();
+        }
+    }
+
+    fn touches(event: &notify::Event, path: &Path) -> bool {
+        !event.kind.is_access() && event.paths.iter().any(|p| p == path)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use notify::event::{AccessKind, EventKind, ModifyKind};
+        use notify::Event;
+
+        #[test]
+        fn only_changes_to_the_watched_file_count() {
+            let path = Path::new("/src/main.lox");
+            let modify = || Event::new(EventKind::Modify(ModifyKind::Any));
+
+            assert!(touches(&modify().add_path(path.into()), path));
+            assert!(!touches(&modify().add_path("/src/other.lox".into()), path));
+            let read = Event::new(EventKind::Access(AccessKind::Any)).add_path(path.into());
+            assert!(!touches(&read, path));
+        }
+    }
+}
+
+#[cfg(not(feature = "watch"))]
+mod watch {
+    use std::path::Path;
+
+    pub fn watch(_path: &Path, _run: impl FnMut()) -> miette::Result<()> {
+        miette::bail!("--watch is unavailable without the watch feature")
+    }
+}
+
+/// Why running a program failed, which decides the exit code.
+enum RunError {
+    /// The program, or a file it imports, doesn't lex or parse, or its imports form a cycle.
+    Malformed(miette::Report),
+    /// Anything else, such as a file that can't be read.
+    Other(miette::Report),
+}
+
+impl RunError {
+    fn wrap_err(self, msg: String) -> Self {
+        match self {
+            Self::Malformed(e) => Self::Malformed(e.wrap_err(msg)),
+            Self::Other(e) => Self::Other(e.wrap_err(msg)),
+        }
+    }
+}
+
+impl From<miette::Report> for RunError {
+    fn from(e: miette::Report) -> Self {
+        Self::Other(e)
+    }
+}
+
+/// Prints a [`RunError::Malformed`] and exits with [`exit_codes::DATA_ERR`], like the other
+/// subcommands do for lexing and parsing errors.
+fn exit_if_malformed(result: Result<(), RunError>) -> miette::Result<()> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(RunError::Malformed(e)) => {
+            eprintln!("{e:?}");
+            std::process::exit(exit_codes::DATA_ERR);
+        }
+        Err(RunError::Other(e)) => Err(e),
+    }
+}
+
+/// Runs the program in `root`, along with everything it imports.
+fn run(root: &Path, options: imp::lex::LexOptions) -> Result<(), RunError> {
+    let mut statements = Vec::new();
+    splice_file(
+        root,
+        options,
+        &mut Vec::new(),
+        &mut HashSet::new(),
+        &mut statements,
+    )?;
+    print_statements(&statements);
+    Ok(())
+}
+
+/// Runs `program` from `-e` as if it were a file named `-e` in the current directory.
+fn run_inline(program: String, options: imp::lex::LexOptions) -> Result<(), RunError> {
+    let root = std::env::current_dir().into_diagnostic()?.join("-e");
+    let mut statements = Vec::new();
+    splice_source(
+        &root,
+        &program,
+        options,
+        &mut Vec::new(),
+        &mut HashSet::new(),
+        &mut statements,
+    )?;
+    print_statements(&statements);
+    Ok(())
+}
+
+fn print_statements(statements: &[String]) {
+    for statement in statements {
+        println!("{statement}");
+    }
+}
+
+/// Prints `tokens` as a table with a header row, each column as wide as its widest cell.
+fn print_token_table(src: &str, tokens: &[imp::lex::Token<'_>]) {
+    let mut line_cols = imp::span::LineCols::new(src);
+    let rows: Vec<[String; 4]> = tokens
+        .iter()
+        .map(|token| {
+            let span = token.span();
+            [
+                token.kind.to_string(),
+                // keep each row on one line, e.g. for strings that span lines
+                token
+                    .origin
+                    .chars()
+                    .map(|c| {
+                        if c.is_control() {
+                            c.escape_debug().to_string()
+                        } else {
+                            c.to_string()
+                        }
+                    })
+                    .collect(),
+                line_cols.line_col(span.start).0.to_string(),
+                format!("{}..{}", span.start, span.end),
+            ]
+        })
+        .collect();
+
+    let header = ["KIND", "LEXEME", "LINE", "SPAN"].map(String::from);
+    let mut widths = [0; 4];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let [kind, lexeme, line, span] = row;
+        let [kind_w, lexeme_w, line_w, _] = widths;
+        println!("{kind:<kind_w$}  {lexeme:<lexeme_w$}  {line:>line_w$}  {span}");
+    }
+}
+
+/// Prints one row per line with tokens, like `line 3: PRINT STRING SEMICOLON`.
+///
+/// A token spanning several lines is listed under the line it starts on.
+fn print_tokens_by_line(src: &str, tokens: &[imp::lex::Token<'_>]) {
+    let mut line_cols = imp::span::LineCols::new(src);
+    let mut current = None;
+    for token in tokens {
+        let (line, _) = line_cols.line_col(token.offset);
+        if current != Some(line) {
+            if current.is_some() {
+                println!();
+            }
+            print!("line {line}:");
+            current = Some(line);
+        }
+        print!(" {}", token.kind);
+    }
+    if current.is_some() {
+        println!();
+    }
+}
+
+fn canonical(path: &Path) -> miette::Result<PathBuf> {
+    fs::canonicalize(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("reading '{}' failed", path.display()))
+}
+
+/// Reads and parses `path` with [`splice_source`], unless it was already spliced in.
+///
+/// `stack` holds the chain of files currently being imported, to detect cycles.
+fn splice_file(
+    path: &Path,
+    options: imp::lex::LexOptions,
+    stack: &mut Vec<PathBuf>,
+    spliced: &mut HashSet<PathBuf>,
+    statements: &mut Vec<String>,
+) -> Result<(), RunError> {
+    if let Some(start) = stack.iter().position(|p| p == path) {
+        let cycle: Vec<_> = stack[start..]
+            .iter()
+            .chain([&path.to_path_buf()])
+            .map(|p| p.display().to_string())
+            .collect();
+        return Err(RunError::Malformed(miette::miette!(
+            "import cycle: {}",
+            cycle.join(" -> ")
+        )));
+    }
+    if spliced.contains(path) {
+        return Ok(());
+    }
+
+    let file_contents = fs::read_to_string(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("reading '{}' failed", path.display()))?;
+    splice_source(path, &file_contents, options, stack, spliced, statements)
+}
+
+/// Parses `file_contents`, read from `path`, onto `statements`, replacing each import with the
+/// imported file's statements.
+///
+/// Every file is parsed once, and spliced in only the first time it is imported. The statements
+/// are kept as they print, since the trees borrow from sources that are dropped along the way.
+fn splice_source(
+    path: &Path,
+    file_contents: &str,
+    options: imp::lex::LexOptions,
+    stack: &mut Vec<PathBuf>,
+    spliced: &mut HashSet<PathBuf>,
+    statements: &mut Vec<String>,
+) -> Result<(), RunError> {
+    spliced.insert(path.to_path_buf());
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let parsed = imp::Parser::new(file_contents)
+        .with_options(options)
+        .with_source_name(path.display().to_string())
+        .parse()
+        .wrap_err_with(|| format!("in '{}'", path.display()))
+        .map_err(RunError::Malformed)?;
+
+    stack.push(path.to_path_buf());
+    for statement in parsed {
+        match statement.import_path() {
+            Some(import) => {
+                let import = canonical(&dir.join(import))?;
+                splice_file(&import, options, stack, spliced, statements)
+                    .map_err(|e| e.wrap_err(format!("imported from '{}'", path.display())))?;
+            }
+            None => statements.push(statement.to_string()),
+        }
+    }
+    stack.pop();
     Ok(())
 }
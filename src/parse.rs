@@ -1,41 +1,512 @@
 use crate::{
-    lex::{Token, TokenKind},
-    Lexer,
+    lex::{LexOptions, Source, Token, TokenKind},
+    Lexer, Span,
 };
-use miette::{Error, LabeledSpan, WrapErr};
-use std::{borrow::Cow, fmt};
+use miette::{Diagnostic, Error, LabeledSpan, SourceSpan, WrapErr};
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use std::{borrow::Cow, fmt, str::FromStr};
+
+#[derive(Diagnostic, Debug, Clone, thiserror::Error)]
+pub enum Warning {
+    #[error("Chained comparison")]
+    #[diagnostic(
+        severity(Warning),
+        help("comparisons don't chain; `a < b < c` compares the result of `a < b` with `c`")
+    )]
+    ChainedComparison {
+        #[source_code]
+        src: Source,
+
+        #[label = "compares the result of the previous comparison"]
+        err_span: SourceSpan,
+    },
+}
+
+#[derive(Diagnostic, Debug, thiserror::Error)]
+#[error("Invalid assignment target")]
+#[diagnostic(help("only variables and fields can be assigned to"))]
+pub struct InvalidAssignmentTarget {
+    #[source_code]
+    src: Source,
+
+    #[label = "cannot assign to this"]
+    err_span: SourceSpan,
+}
+
+#[derive(Diagnostic, Debug, thiserror::Error)]
+#[error("Unclosed bracketed expression")]
+#[diagnostic(help("add a `)` to close it"))]
+pub struct UnclosedGroup {
+    #[source_code]
+    src: Source,
+
+    #[label = "this ( is never closed"]
+    err_span: SourceSpan,
+}
+
+#[derive(Diagnostic, Debug, thiserror::Error)]
+#[error("Expression too deeply nested")]
+#[diagnostic(help("the parser gives up past {max_depth} levels of nesting"))]
+pub struct TooDeeplyNested {
+    #[source_code]
+    src: Source,
+
+    #[label = "this is nested too deeply"]
+    err_span: SourceSpan,
+
+    max_depth: usize,
+}
+
+#[derive(Diagnostic, Debug, thiserror::Error)]
+#[error("Expected {expected}, found {found}")]
+pub struct UnexpectedToken {
+    #[source_code]
+    src: Source,
+
+    #[label("found {found} here")]
+    err_span: SourceSpan,
+
+    pub expected: TokenKind,
+    pub found: TokenKind,
+}
+
+impl UnexpectedToken {
+    /// The 1-based line and column of the token that was found instead.
+    pub fn line_col(&self) -> (usize, usize) {
+        Span::from(self.err_span).line_col(self.src.text())
+    }
+}
+
+#[derive(Diagnostic, Debug, thiserror::Error)]
+#[error("`{keyword}` outside of a loop")]
+#[diagnostic(help("`break` and `continue` only work in the body of a `while` or `for` loop"))]
+pub struct OutsideLoop {
+    #[source_code]
+    src: Source,
+
+    #[label = "not inside a loop"]
+    err_span: SourceSpan,
+
+    pub keyword: Op,
+}
 
 pub struct Parser<'de> {
     whole: &'de str,
     lexer: Lexer<'de>,
+    warnings: Option<Vec<Warning>>,
+    depth: usize,
+    max_depth: usize,
+    /// How many loop bodies enclose the current statement, within the innermost function.
+    loop_depth: usize,
+    /// Whether the current statement sits directly in a class body, where `get` declares getters.
+    class_body: bool,
 }
 
 pub struct Ast;
 
+/// One line typed into a REPL, from [`Parser::parse_repl_line`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplItem<'de> {
+    Statement(TokenTree<'de>),
+    /// A bare expression, whose value the REPL should print.
+    Expression(TokenTree<'de>),
+}
+
 impl<'de> Parser<'de> {
     pub fn new(input: &'de str) -> Self {
         Self {
             whole: input,
             lexer: Lexer::new(input),
+            warnings: None,
+            depth: 0,
+            max_depth: 256,
+            loop_depth: 0,
+            class_body: false,
+        }
+    }
+
+    /// Lex with `options`, except for the trivia and newline tokens the grammar has no place for.
+    pub fn with_options(mut self, options: LexOptions) -> Self {
+        self.lexer = self.lexer.with_options(LexOptions {
+            trivia: false,
+            emit_newlines: false,
+            ..options
+        });
+        self
+    }
+
+    /// Name diagnostics after `name`, usually the path the input was read from.
+    pub fn with_source_name(mut self, name: impl Into<String>) -> Self {
+        self.lexer = self.lexer.with_source_name(name);
+        self
+    }
+
+    /// Collect non-fatal [`Warning`]s while parsing, available afterwards from [`Parser::warnings`].
+    pub fn with_warnings(mut self) -> Self {
+        self.warnings = Some(Vec::new());
+        self
+    }
+
+    /// Record the tokens consumed while parsing, available afterwards from [`Parser::tokens`].
+    pub fn with_token_recording(mut self) -> Self {
+        self.lexer = self.lexer.with_recording();
+        self
+    }
+
+    /// Fail with [`TooDeeplyNested`] rather than recurse more than `max_depth` levels deep.
+    ///
+    /// Defaults to 256, which fits comfortably on the main thread's stack.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = &Token<'de>> {
+        self.lexer.recorded().iter()
+    }
+
+    pub fn warnings(&self) -> &[Warning] {
+        self.warnings.as_deref().unwrap_or_default()
+    }
+
+    fn warn(&mut self, warning: Warning) {
+        if let Some(warnings) = &mut self.warnings {
+            warnings.push(warning);
         }
     }
 
-    pub fn parse_expression(mut self) -> Result<TokenTree<'de>, Error> {
+    pub fn parse_expression(&mut self) -> Result<TokenTree<'de>, Error> {
         self.parse_expression_within(0)
     }
 
-    pub fn parse(mut self) -> Result<TokenTree<'de>, Error> {
-        // TODO: in a loop
-        self.parse_statement_within(0)
+    pub fn parse(&mut self) -> Result<Vec<TokenTree<'de>>, Error> {
+        let mut statements = Vec::new();
+        while self.lexer.peek().is_some() {
+            let statement = self.parse_terminated_statement(statements.len() + 1)?;
+            statements.push(statement);
+        }
+
+        Ok(statements)
+    }
+
+    /// Parses the whole input as one statement or one bare expression, with or without a
+    /// trailing `;`.
+    pub fn parse_repl_line(&mut self) -> Result<ReplItem<'de>, Error> {
+        let is_statement = matches!(
+            self.lexer.peek(),
+            Some(Ok(Token {
+                kind: TokenKind::Print
+                    | TokenKind::Return
+                    | TokenKind::For
+                    | TokenKind::While
+                    | TokenKind::Class
+                    | TokenKind::Var
+                    | TokenKind::Fun
+                    | TokenKind::Import
+                    | TokenKind::Mod
+                    | TokenKind::Break
+                    | TokenKind::Continue
+                    | TokenKind::Use
+                    | TokenKind::Assert
+                    | TokenKind::If,
+                ..
+            }))
+        );
+        let item = if is_statement {
+            ReplItem::Statement(self.parse_statement_within(0)?)
+        } else {
+            ReplItem::Expression(self.parse_expression_within(0)?)
+        };
+
+        if matches!(
+            self.lexer.peek(),
+            Some(Ok(Token {
+                kind: TokenKind::Semicolon,
+                ..
+            }))
+        ) {
+            self.lexer.next();
+        }
+        if let Some(token) = self.lexer.next() {
+            let token = token?;
+            return Err(miette::miette! {
+                labels = vec![
+                    LabeledSpan::at(token.span(), "here"),
+                ],
+                help = "a REPL line holds a single statement or expression",
+                "Unexpected input after the end of the line",
+            }
+            .with_source_code(self.lexer.source()));
+        }
+
+        Ok(item)
+    }
+
+    /// Checks the whole input without keeping any syntax trees around.
+    ///
+    /// Unlike [`Parser::parse`], this does not stop at the first error: it skips past the failing
+    /// statement's `;` and carries on, so every statement gets checked.
+    pub fn validate(&mut self) -> Vec<Error> {
+        let mut errors = Vec::new();
+        let mut n = 0;
+        while self.lexer.peek().is_some() {
+            n += 1;
+            if let Err(e) = self.parse_terminated_statement(n) {
+                errors.push(e);
+                self.skip_past_semicolon();
+            }
+        }
+
+        errors
+    }
+
+    /// Parses statement #`n`, along with its `;` unless it ends in a block.
+    fn parse_terminated_statement(&mut self, n: usize) -> Result<TokenTree<'de>, Error> {
+        let statement = self.parse_statement_within(0)?;
+
+        let ends_in_block = match &statement {
+            TokenTree::Fun { .. } | TokenTree::If { .. } => true,
+            TokenTree::Cons(op, _) => {
+                matches!(op, Op::For | Op::While | Op::Class | Op::Get | Op::Mod)
+            }
+            _ => false,
+        };
+        if !ends_in_block {
+            self.expect(TokenKind::Semicolon)
+                .wrap_err_with(|| format!("after statement #{n}"))?;
+        }
+
+        Ok(statement)
+    }
+
+    fn skip_past_semicolon(&mut self) {
+        // the statement may have failed on its `;` itself, as in `var x = ;`
+        if self.lexer.last_kind() == Some(TokenKind::Semicolon) {
+            return;
+        }
+        for token in self.lexer.by_ref() {
+            if matches!(
+                token,
+                Ok(Token {
+                    kind: TokenKind::Semicolon,
+                    ..
+                })
+            ) {
+                break;
+            }
+        }
     }
 
+    /// Parses `{ ... }` holding any number of statements, each terminated as at the top level.
     pub fn parse_block(&mut self) -> Result<TokenTree<'de>, Error> {
-        self.lexer.expect(TokenKind::LeftBrace, "missing {")?;
-        // TODO: in a loop with semicolons? depends on class vs body
-        let block = self.parse_statement_within(0)?;
-        self.lexer.expect(TokenKind::RightBrace, "missing }")?;
+        self.parse_block_of(false)
+    }
+
+    /// Like [`Parser::parse_block_outside_loop`], for a class body, whose statements may
+    /// declare getters.
+    fn parse_class_body(&mut self) -> Result<TokenTree<'de>, Error> {
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let block = self.parse_block_of(true);
+        self.loop_depth = loop_depth;
+        block
+    }
+
+    fn parse_block_of(&mut self, class_body: bool) -> Result<TokenTree<'de>, Error> {
+        self.expect(TokenKind::LeftBrace)?;
+
+        let outer = std::mem::replace(&mut self.class_body, class_body);
+        let statements = self.parse_statements_until_brace();
+        self.class_body = outer;
+        let statements = statements?;
+        self.expect(TokenKind::RightBrace)?;
+
+        Ok(TokenTree::Cons(Op::Block, statements))
+    }
+
+    fn parse_statements_until_brace(&mut self) -> Result<Vec<TokenTree<'de>>, Error> {
+        let mut statements = Vec::new();
+        loop {
+            match self.lexer.peek() {
+                Some(Ok(Token {
+                    kind: TokenKind::RightBrace,
+                    ..
+                })) => break,
+                None => return Err(self.lexer.unexpected_eof("expected RIGHT_BRACE")),
+                _ => {}
+            }
+            let statement = self.parse_terminated_statement(statements.len() + 1)?;
+            statements.push(statement);
+        }
+
+        Ok(statements)
+    }
+
+    /// Consumes the next token if it is an `expected` one, or fails with [`UnexpectedToken`].
+    pub fn expect(&mut self, expected: TokenKind) -> Result<Token<'de>, Error> {
+        match self.lexer.next() {
+            Some(Ok(token)) if token.kind.tag() == expected.tag() => Ok(token),
+            Some(Ok(token)) => Err(UnexpectedToken {
+                src: self.lexer.source(),
+                err_span: token.span().into(),
+                expected,
+                found: token.kind,
+            }
+            .into()),
+            Some(Err(e)) => Err(e),
+            None => Err(self.lexer.unexpected_eof(&format!("expected {expected}"))),
+        }
+    }
+
+    /// Parses the body of a `while` or `for` loop, where `break` and `continue` are allowed.
+    fn parse_loop_body(&mut self) -> Result<TokenTree<'de>, Error> {
+        self.loop_depth += 1;
+        let block = self.parse_block();
+        self.loop_depth -= 1;
+        block
+    }
+
+    /// Parses a function or class body, which a surrounding loop can't be broken out of from.
+    fn parse_block_outside_loop(&mut self) -> Result<TokenTree<'de>, Error> {
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let block = self.parse_block();
+        self.loop_depth = loop_depth;
+        block
+    }
+
+    /// Parses the parameter list and body of a function, after any name; `what` names the
+    /// function in errors, like `function f`.
+    fn parse_function_rest(
+        &mut self,
+        what: &str,
+    ) -> Result<(Vec<Token<'de>>, TokenTree<'de>), Error> {
+        let mut parameters = Vec::new();
+
+        self.expect(TokenKind::LeftParen)
+            .wrap_err_with(|| format!("in parameter list of {what}"))?;
+
+        if matches!(
+            self.lexer.peek(),
+            Some(Ok(Token {
+                kind: TokenKind::RightParen,
+                ..
+            }))
+        ) {
+            // immediate parameter list end
+            self.lexer.next();
+        } else {
+            loop {
+                let parameter = self.expect(TokenKind::Ident).wrap_err_with(|| {
+                    format!("in parameter #{} of {what}", parameters.len() + 1)
+                })?;
+                parameters.push(parameter);
+
+                let token = self
+                    .lexer
+                    .expect_where(
+                        |token| matches!(token.kind, TokenKind::RightParen | TokenKind::Comma),
+                        "continuing parameter list",
+                    )
+                    .wrap_err_with(|| format!("in parameter list of {what}"))?;
+
+                if token.kind == TokenKind::RightParen {
+                    break;
+                }
+            }
+        }
+
+        let block = self
+            .parse_block_outside_loop()
+            .wrap_err_with(|| format!("in body of {what}"))?;
+
+        Ok((parameters, block))
+    }
+
+    /// Parses an `if` statement after its `if`, or an `elif` branch after its `elif`.
+    fn parse_if_tail(&mut self) -> Result<TokenTree<'de>, Error> {
+        self.expect(TokenKind::LeftParen)
+            .wrap_err("in if condition")?;
+
+        let cond = self
+            .parse_expression_within(0)
+            .wrap_err("in if loop condition")?;
+
+        self.expect(TokenKind::RightParen)
+            .wrap_err("in if loop condition")?;
+
+        let block = self.parse_block().wrap_err("in body of if")?;
+
+        let otherwise = match self.lexer.peek() {
+            Some(Ok(Token {
+                kind: TokenKind::Else,
+                ..
+            })) => {
+                self.lexer.next();
+
+                if matches!(
+                    self.lexer.peek(),
+                    Some(Ok(Token {
+                        kind: TokenKind::If,
+                        ..
+                    }))
+                ) {
+                    Some(self.parse_statement_within(0).wrap_err("in else if")?)
+                } else {
+                    Some(self.parse_block().wrap_err("in body of else")?)
+                }
+            }
+            // only lexed with `LexOptions::elif_keyword`
+            Some(Ok(Token {
+                kind: TokenKind::Elif,
+                ..
+            })) => {
+                self.lexer.next();
+                Some(
+                    self.descend(|parser| parser.parse_if_tail())
+                        .wrap_err("in elif")?,
+                )
+            }
+            _ => None,
+        };
+
+        Ok(TokenTree::If {
+            condition: Box::new(cond),
+            yes: Box::new(block),
+            no: otherwise.map(Box::new),
+        })
+    }
+
+    /// Only variables and fields can be assigned to; `target` spans `start` up to the `=`.
+    fn check_assignment_target(
+        &self,
+        target: &TokenTree<'de>,
+        start: usize,
+        equals: usize,
+    ) -> Result<(), Error> {
+        match target {
+            TokenTree::Atom(Atom::Ident(_), _) | TokenTree::Cons(Op::Field, _) => Ok(()),
+            _ => {
+                let end = start + self.whole[start..equals].trim_end().len();
+                Err(InvalidAssignmentTarget {
+                    src: self.lexer.source(),
+                    err_span: SourceSpan::from(start..end),
+                }
+                .into())
+            }
+        }
+    }
 
-        Ok(block)
+    /// Expects the `)` closing a group opened at byte `open`; if the input ends first, the error
+    /// covers the whole unclosed group.
+    fn expect_group_close(&mut self, open: usize) -> Result<Token<'de>, Error> {
+        if self.lexer.peek().is_none() {
+            let end = self.whole.trim_end().len();
+            return Err(UnclosedGroup {
+                src: self.lexer.source(),
+                err_span: Span::new(open, open + 1).merge(Span::new(end, end)).into(),
+            }
+            .into());
+        }
+        self.expect(TokenKind::RightParen)
     }
 
     pub fn parse_fun_call_arguments(&mut self) -> Result<Vec<TokenTree<'de>>, Error> {
@@ -51,6 +522,7 @@ impl<'de> Parser<'de> {
             }))
         ) {
             // immediate argument list end
+            self.lexer.next();
         } else {
             loop {
                 let argument = self.parse_expression_within(0).wrap_err_with(|| {
@@ -75,31 +547,142 @@ impl<'de> Parser<'de> {
         Ok(arguments)
     }
 
+    /// Parses the `key: value` pairs of a map literal up to its `}`; keys are strings or numbers.
+    pub fn parse_map_entries(&mut self) -> Result<Vec<(TokenTree<'de>, TokenTree<'de>)>, Error> {
+        let mut entries = Vec::new();
+
+        // parent has already eaten the left brace
+
+        if matches!(
+            self.lexer.peek(),
+            Some(Ok(Token {
+                kind: TokenKind::RightBrace,
+                ..
+            }))
+        ) {
+            self.lexer.next();
+            return Ok(entries);
+        }
+
+        loop {
+            let n = entries.len() + 1;
+            let key = self
+                .lexer
+                .expect_where(
+                    |token| matches!(token.kind, TokenKind::String | TokenKind::Number { .. }),
+                    "expected a string or number key",
+                )
+                .wrap_err_with(|| format!("in key of entry #{n}"))?;
+            let key = match key.kind {
+                TokenKind::Number { value, .. } => {
+                    TokenTree::Atom(Atom::Number(value), key.span().into())
+                }
+                _ => TokenTree::Atom(Atom::String(Token::unescape(key.origin)), key.span().into()),
+            };
+
+            self.expect(TokenKind::Colon)
+                .wrap_err_with(|| format!("after key of entry #{n}"))?;
+
+            let value = self
+                .parse_expression_within(0)
+                .wrap_err_with(|| format!("in value of entry #{n}"))?;
+            entries.push((key, value));
+
+            let token = self.lexer.expect_where(
+                |token| matches!(token.kind, TokenKind::RightBrace | TokenKind::Comma),
+                "continuing map entries",
+            )?;
+
+            if token.kind == TokenKind::RightBrace {
+                break;
+            }
+        }
+
+        Ok(entries)
+    }
+
     pub fn parse_statement_within(&mut self, min_bp: u8) -> Result<TokenTree<'de>, Error> {
+        self.descend(|parser| parser.parse_statement_inner(min_bp))
+    }
+
+    pub fn parse_expression_within(&mut self, min_bp: u8) -> Result<TokenTree<'de>, Error> {
+        self.descend(|parser| parser.parse_expression_inner(min_bp))
+    }
+
+    /// Runs `parse` one level deeper, unless that would pass the maximum depth.
+    fn descend(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<TokenTree<'de>, Error>,
+    ) -> Result<TokenTree<'de>, Error> {
+        if self.depth >= self.max_depth {
+            let err_span = match self.lexer.peek() {
+                Some(Ok(token)) => SourceSpan::from(token.span()),
+                _ => SourceSpan::from(self.whole.len()..self.whole.len()),
+            };
+            return Err(TooDeeplyNested {
+                src: self.lexer.source(),
+                err_span,
+                max_depth: self.max_depth,
+            }
+            .into());
+        }
+
+        self.depth += 1;
+        let tree = parse(self);
+        self.depth -= 1;
+        tree
+    }
+
+    fn parse_statement_inner(&mut self, min_bp: u8) -> Result<TokenTree<'de>, Error> {
         let lhs = match self.lexer.next() {
             Some(Ok(token)) => token,
-            None => return Ok(TokenTree::Atom(Atom::Nil)),
+            None => return Err(self.lexer.unexpected_eof("expected statement")),
             Some(Err(e)) => {
                 return Err(e).wrap_err("on the left-hand side");
             }
         };
 
+        let start = lhs.offset;
+        let span = LeafSpan(lhs.span());
         let mut lhs = match lhs {
+            // `get name { ... }` in a class body declares a getter; `get` on its own, or outside
+            // a class, is still a variable
+            token
+                if self.class_body
+                    && token.is_contextual_keyword("get")
+                    && matches!(
+                        self.lexer.peek(),
+                        Some(Ok(Token {
+                            kind: TokenKind::Ident,
+                            ..
+                        }))
+                    ) =>
+            {
+                let name = self.expect(TokenKind::Ident)?;
+                let ident = TokenTree::Atom(Atom::Ident(name.origin), name.span().into());
+
+                let block = self
+                    .parse_block_outside_loop()
+                    .wrap_err_with(|| format!("in body of getter {}", name.origin))?;
+
+                return Ok(TokenTree::Cons(Op::Get, vec![ident, block]));
+            }
+
             Token {
                 kind: TokenKind::Ident,
                 origin,
                 ..
-            } => TokenTree::Atom(Atom::Ident(origin)),
+            } => TokenTree::Atom(Atom::Ident(origin), span),
 
             Token {
                 kind: TokenKind::Super,
                 ..
-            } => TokenTree::Atom(Atom::Super),
+            } => TokenTree::Atom(Atom::Super, span),
 
             Token {
                 kind: TokenKind::This,
                 ..
-            } => TokenTree::Atom(Atom::This),
+            } => TokenTree::Atom(Atom::This, span),
 
             Token {
                 kind: TokenKind::LeftParen,
@@ -109,11 +692,7 @@ impl<'de> Parser<'de> {
                     .parse_expression_within(0)
                     .wrap_err("in bracketed expression")?;
 
-                self.lexer
-                    .expect(
-                        TokenKind::RightParen,
-                        "Unexpected end to bracketed expression",
-                    )
+                self.expect_group_close(start)
                     .wrap_err("after bracketed expression")?;
 
                 TokenTree::Cons(Op::Group, vec![lhs])
@@ -133,42 +712,56 @@ impl<'de> Parser<'de> {
                 let rhs = self
                     .parse_expression_within(r_bp)
                     .wrap_err_with(|| format!("on the right-hand side of {op:?}"))?;
-                return Ok(TokenTree::Cons(op, vec![rhs]));
+
+                // `print a, b, c` prints all of them, separated by spaces
+                let mut arguments = vec![rhs];
+                while op == Op::Print
+                    && matches!(
+                        self.lexer.peek(),
+                        Some(Ok(Token {
+                            kind: TokenKind::Comma,
+                            ..
+                        }))
+                    )
+                {
+                    self.lexer.next();
+                    let argument = self.parse_expression_within(r_bp).wrap_err_with(|| {
+                        format!("in argument #{} of Print", arguments.len() + 1)
+                    })?;
+                    arguments.push(argument);
+                }
+                return Ok(TokenTree::Cons(op, arguments));
             }
 
             Token {
                 kind: TokenKind::For,
                 ..
             } => {
-                self.lexer
-                    .expect(TokenKind::LeftParen, "missing (")
+                self.expect(TokenKind::LeftParen)
                     .wrap_err("in for loop condition")?;
 
                 let init = self
                     .parse_expression_within(0)
                     .wrap_err("in init condition of for loop")?;
 
-                self.lexer
-                    .expect(TokenKind::Semicolon, "missing ;")
+                self.expect(TokenKind::Semicolon)
                     .wrap_err("in for loop condition")?;
 
                 let cond = self
                     .parse_expression_within(0)
                     .wrap_err("in loop condition of for loop")?;
 
-                self.lexer
-                    .expect(TokenKind::Semicolon, "missing ;")
+                self.expect(TokenKind::Semicolon)
                     .wrap_err("in for loop condition")?;
 
                 let inc = self
                     .parse_expression_within(0)
                     .wrap_err("in incremental condition of for loop")?;
 
-                self.lexer
-                    .expect(TokenKind::RightParen, "missing )")
+                self.expect(TokenKind::RightParen)
                     .wrap_err("in for loop condition")?;
 
-                let block = self.parse_block().wrap_err("in body of for loop")?;
+                let block = self.parse_loop_body().wrap_err("in body of for loop")?;
 
                 return Ok(TokenTree::Cons(Op::For, vec![init, cond, inc, block]));
             }
@@ -177,19 +770,17 @@ impl<'de> Parser<'de> {
                 kind: TokenKind::While,
                 ..
             } => {
-                self.lexer
-                    .expect(TokenKind::LeftParen, "missing (")
+                self.expect(TokenKind::LeftParen)
                     .wrap_err("in while loop condition")?;
 
                 let cond = self
                     .parse_expression_within(0)
                     .wrap_err("in while loop condition")?;
 
-                self.lexer
-                    .expect(TokenKind::RightParen, "missing )")
+                self.expect(TokenKind::RightParen)
                     .wrap_err("in while loop condition")?;
 
-                let block = self.parse_block().wrap_err("in body of while loop")?;
+                let block = self.parse_loop_body().wrap_err("in body of while loop")?;
 
                 return Ok(TokenTree::Cons(Op::While, vec![cond, block]));
             }
@@ -198,20 +789,16 @@ impl<'de> Parser<'de> {
                 kind: TokenKind::Class,
                 ..
             } => {
-                let token = self
-                    .lexer
-                    .expect(TokenKind::Ident, "expected identifier")
-                    .wrap_err("in class name")?;
+                let token = self.expect(TokenKind::Ident).wrap_err("in class name")?;
                 assert_eq!(token.kind, TokenKind::Ident);
-                let ident = TokenTree::Atom(Atom::Ident(token.origin));
+                let ident = TokenTree::Atom(Atom::Ident(token.origin), token.span().into());
 
                 if lhs.kind == TokenKind::Var {
-                    self.lexer
-                        .expect(TokenKind::Equal, "missing =")
+                    self.expect(TokenKind::Equal)
                         .wrap_err("in variable assignment")?;
                 }
 
-                let block = self.parse_block().wrap_err("in class definition")?;
+                let block = self.parse_class_body().wrap_err("in class definition")?;
 
                 return Ok(TokenTree::Cons(Op::Class, vec![ident, block]));
             }
@@ -221,14 +808,12 @@ impl<'de> Parser<'de> {
                 ..
             } => {
                 let token = self
-                    .lexer
-                    .expect(TokenKind::Ident, "expected identifier")
+                    .expect(TokenKind::Ident)
                     .wrap_err("in variable assignment")?;
                 assert_eq!(token.kind, TokenKind::Ident);
-                let ident = TokenTree::Atom(Atom::Ident(token.origin));
+                let ident = TokenTree::Atom(Atom::Ident(token.origin), token.span().into());
 
-                self.lexer
-                    .expect(TokenKind::Equal, "missing =")
+                self.expect(TokenKind::Equal)
                     .wrap_err("in variable assignment")?;
 
                 let second = self
@@ -243,56 +828,13 @@ impl<'de> Parser<'de> {
                 ..
             } => {
                 let token = self
-                    .lexer
-                    .expect(TokenKind::Ident, "expected identifier")
+                    .expect(TokenKind::Ident)
                     .wrap_err("in function name declaration")?;
                 assert_eq!(token.kind, TokenKind::Ident);
                 let name = token.origin;
                 let ident = Atom::Ident(token.origin);
 
-                let mut parameters = Vec::new();
-
-                self.lexer
-                    .expect(TokenKind::LeftParen, "missing (")
-                    .wrap_err_with(|| format!("in parameter list of function {name}"))?;
-
-                if matches!(
-                    self.lexer.peek(),
-                    Some(Ok(Token {
-                        kind: TokenKind::RightParen,
-                        ..
-                    }))
-                ) {
-                    // immediate parameter list end
-                } else {
-                    loop {
-                        let parameter = self
-                            .lexer
-                            .expect(TokenKind::Ident, "unexpected token")
-                            .wrap_err_with(|| {
-                                format!("in parameter #{} of function {name}", parameters.len() + 1)
-                            })?;
-                        parameters.push(parameter);
-
-                        let token = self
-                            .lexer
-                            .expect_where(
-                                |token| {
-                                    matches!(token.kind, TokenKind::RightParen | TokenKind::Comma)
-                                },
-                                "continuing parameter list",
-                            )
-                            .wrap_err_with(|| format!("in parameter list of function {name}"))?;
-
-                        if token.kind == TokenKind::RightParen {
-                            break;
-                        }
-                    }
-                }
-
-                let block = self
-                    .parse_block()
-                    .wrap_err_with(|| format!("in body of function {name}"))?;
+                let (parameters, block) = self.parse_function_rest(&format!("function {name}"))?;
 
                 return Ok(TokenTree::Fun {
                     name: ident,
@@ -302,43 +844,113 @@ impl<'de> Parser<'de> {
             }
 
             Token {
-                kind: TokenKind::If,
+                kind: TokenKind::Import,
                 ..
             } => {
-                self.lexer
-                    .expect(TokenKind::LeftParen, "missing (")
-                    .wrap_err("in if condition")?;
+                let path = self
+                    .expect(TokenKind::String)
+                    .wrap_err("in import statement")?;
+
+                return Ok(TokenTree::Cons(
+                    Op::Import,
+                    vec![TokenTree::Atom(
+                        Atom::String(Token::unescape(path.origin)),
+                        path.span().into(),
+                    )],
+                ));
+            }
 
-                let cond = self
-                    .parse_expression_within(0)
-                    .wrap_err("in if loop condition")?;
+            Token {
+                kind: TokenKind::Mod,
+                ..
+            } => {
+                let token = self.expect(TokenKind::Ident).wrap_err("in module name")?;
+                let ident = TokenTree::Atom(Atom::Ident(token.origin), token.span().into());
 
-                self.lexer
-                    .expect(TokenKind::RightParen, "missing )")
-                    .wrap_err("in if loop condition")?;
+                let block = self
+                    .parse_block()
+                    .wrap_err_with(|| format!("in body of module {}", token.origin))?;
 
-                let block = self.parse_block().wrap_err("in body of if")?;
+                return Ok(TokenTree::Cons(Op::Mod, vec![ident, block]));
+            }
 
-                let mut otherwise = None;
-                if matches!(
-                    self.lexer.peek(),
-                    Some(Ok(Token {
-                        kind: TokenKind::Else,
-                        ..
-                    }))
-                ) {
+            // `use a::b::c;` keeps each segment of the path as an identifier
+            Token {
+                kind: TokenKind::Use,
+                ..
+            } => {
+                let mut path = Vec::new();
+                loop {
+                    let token = self.expect(TokenKind::Ident).wrap_err("in use path")?;
+                    path.push(TokenTree::Atom(
+                        Atom::Ident(token.origin),
+                        token.span().into(),
+                    ));
+
+                    if !matches!(
+                        self.lexer.peek(),
+                        Some(Ok(Token {
+                            kind: TokenKind::ColonColon,
+                            ..
+                        }))
+                    ) {
+                        break;
+                    }
                     self.lexer.next();
-
-                    otherwise = Some(self.parse_block().wrap_err("in body of else")?);
                 }
 
-                return Ok(TokenTree::If {
-                    condition: Box::new(cond),
-                    yes: Box::new(block),
-                    no: otherwise.map(Box::new),
+                return Ok(TokenTree::Cons(Op::Use, path));
+            }
+
+            Token {
+                kind: TokenKind::Assert,
+                ..
+            } => {
+                let start = match self.lexer.peek() {
+                    Some(Ok(token)) => token.offset,
+                    _ => self.whole.len(),
+                };
+                let condition = self
+                    .parse_expression_within(0)
+                    .wrap_err("in assert statement")?;
+                // the end of the condition's last token, leaving out any comment after it
+                let end = self
+                    .lexer
+                    .last_end()
+                    .expect("the condition parsed, so it took at least one token");
+
+                return Ok(TokenTree::Assert {
+                    condition: Box::new(condition),
+                    text: &self.whole[start..end],
                 });
             }
 
+            Token {
+                kind: TokenKind::If,
+                ..
+            } => return self.parse_if_tail(),
+
+            Token {
+                kind: TokenKind::Break | TokenKind::Continue,
+                ..
+            } => {
+                let op = match lhs.kind {
+                    TokenKind::Break => Op::Break,
+                    TokenKind::Continue => Op::Continue,
+                    _ => unreachable!("by the outer match arm pattern"),
+                };
+                if self.loop_depth == 0 {
+                    return Err(OutsideLoop {
+                        src: self.lexer.source(),
+                        err_span: lhs.span().into(),
+                        keyword: op,
+                    }
+                    .into());
+                }
+
+                return Ok(TokenTree::Cons(op, Vec::new()));
+            }
+
             token => {
                 return Err(miette::miette! {
                     labels = vec![
@@ -347,13 +959,13 @@ impl<'de> Parser<'de> {
                     help = format!("Unexpected {token:?}"),
                     "Expected a statement",
                 }
-                .with_source_code(self.whole.to_string()))
+                .with_source_code(self.lexer.source()))
             }
         };
 
         loop {
             let op = self.lexer.peek();
-            if op.map_or(false, |op| op.is_err()) {
+            if op.is_some_and(|op| op.is_err()) {
                 return Err(self
                     .lexer
                     .next()
@@ -363,6 +975,10 @@ impl<'de> Parser<'de> {
             }
             let op = match op.map(|res| res.as_ref().expect("handled Err above")) {
                 None => break,
+                Some(Token {
+                    kind: TokenKind::Semicolon | TokenKind::RightBrace,
+                    ..
+                }) => break,
                 Some(Token {
                     kind: TokenKind::LeftParen,
                     ..
@@ -371,6 +987,10 @@ impl<'de> Parser<'de> {
                     kind: TokenKind::Dot,
                     ..
                 }) => Op::Field,
+                Some(Token {
+                    kind: TokenKind::Equal,
+                    ..
+                }) => Op::Assign,
 
                 Some(token) => return Err(miette::miette! {
                     labels = vec![
@@ -379,7 +999,7 @@ impl<'de> Parser<'de> {
                     help = format!("Unexpected {token:?}"),
                     "Expected an operator",
                 }
-                .with_source_code(self.whole.to_string())),
+                .with_source_code(self.lexer.source())),
             };
 
             if let Some((l_bp, ())) = postfix_binding_power(op) {
@@ -404,7 +1024,15 @@ impl<'de> Parser<'de> {
                 if l_bp < min_bp {
                     break;
                 }
-                self.lexer.next();
+                let op_token = self
+                    .lexer
+                    .next()
+                    .expect("checked Some above")
+                    .expect("handled Err above");
+
+                if op == Op::Assign {
+                    self.check_assignment_target(&lhs, start, op_token.offset)?;
+                }
 
                 let rhs = self
                     .parse_expression_within(r_bp)
@@ -419,14 +1047,16 @@ impl<'de> Parser<'de> {
         Ok(lhs)
     }
 
-    pub fn parse_expression_within(&mut self, min_bp: u8) -> Result<TokenTree<'de>, Error> {
+    fn parse_expression_inner(&mut self, min_bp: u8) -> Result<TokenTree<'de>, Error> {
         let lhs = match self.lexer.next() {
             Some(Ok(token)) => token,
-            None => return Ok(TokenTree::Atom(Atom::Nil)),
+            None => return Err(self.lexer.unexpected_eof("expected expression")),
             Some(Err(e)) => {
                 return Err(e).wrap_err("on left-hand side");
             }
         };
+        let start = lhs.offset;
+        let span = LeafSpan(lhs.span());
         let mut lhs =
             match lhs {
                 // atoms
@@ -434,37 +1064,37 @@ impl<'de> Parser<'de> {
                     kind: TokenKind::String,
                     origin,
                     ..
-                } => TokenTree::Atom(Atom::String(Token::unescape(origin))),
+                } => TokenTree::Atom(Atom::String(Token::unescape(origin)), span),
                 Token {
-                    kind: TokenKind::Number(n),
+                    kind: TokenKind::Number { value: n, .. },
                     ..
-                } => TokenTree::Atom(Atom::Number(n)),
+                } => TokenTree::Atom(Atom::Number(n), span),
                 Token {
                     kind: TokenKind::True,
                     ..
-                } => TokenTree::Atom(Atom::Bool(true)),
+                } => TokenTree::Atom(Atom::Bool(true), span),
                 Token {
                     kind: TokenKind::False,
                     ..
-                } => TokenTree::Atom(Atom::Bool(false)),
+                } => TokenTree::Atom(Atom::Bool(false), span),
                 Token {
                     kind: TokenKind::Nil,
                     ..
-                } => TokenTree::Atom(Atom::Nil),
+                } => TokenTree::Atom(Atom::Nil, span),
                 Token {
                     kind: TokenKind::Ident,
                     origin,
                     ..
-                } => TokenTree::Atom(Atom::Ident(origin)),
+                } => TokenTree::Atom(Atom::Ident(origin), span),
                 Token {
                     kind: TokenKind::Super,
                     ..
-                } => TokenTree::Atom(Atom::Super),
+                } => TokenTree::Atom(Atom::Super, span),
 
                 Token {
                     kind: TokenKind::This,
                     ..
-                } => TokenTree::Atom(Atom::This),
+                } => TokenTree::Atom(Atom::This, span),
 
                 // groups
                 Token {
@@ -474,14 +1104,26 @@ impl<'de> Parser<'de> {
                     let lhs = self
                         .parse_expression_within(0)
                         .wrap_err("in bracketed expression")?;
-                    self.lexer
-                        .expect(
-                            TokenKind::RightParen,
-                            "Unexpected end to bracketed expression",
-                        )
+                    self.expect_group_close(start)
                         .wrap_err("after bracketed expression")?;
                     TokenTree::Cons(Op::Group, vec![lhs])
                 }
+                // an anonymous function, like `fun (x) { return x }`
+                Token {
+                    kind: TokenKind::Fun,
+                    ..
+                } => {
+                    let (parameters, body) = self.parse_function_rest("anonymous function")?;
+                    TokenTree::Lambda {
+                        parameters,
+                        body: Box::new(body),
+                    }
+                }
+                // blocks only follow statement keywords, so `{` here starts a map
+                Token {
+                    kind: TokenKind::LeftBrace,
+                    ..
+                } => TokenTree::Map(self.parse_map_entries().wrap_err("in map literal")?),
 
                 // unary prefix expressions
                 Token {
@@ -507,12 +1149,12 @@ impl<'de> Parser<'de> {
                     help = format!("Unexpected {token:?}"),
                     "Expected an expression",
                 }
-                .with_source_code(self.whole.to_string())),
+                .with_source_code(self.lexer.source())),
             };
 
         loop {
             let op = self.lexer.peek();
-            if op.map_or(false, |op| op.is_err()) {
+            if op.is_some_and(|op| op.is_err()) {
                 return Err(self
                     .lexer
                     .next()
@@ -551,6 +1193,10 @@ impl<'de> Parser<'de> {
                     kind: TokenKind::Star,
                     ..
                 }) => Op::Star,
+                Some(Token {
+                    kind: TokenKind::StarStar,
+                    ..
+                }) => Op::StarStar,
                 Some(Token {
                     kind: TokenKind::BangEqual,
                     ..
@@ -559,6 +1205,14 @@ impl<'de> Parser<'de> {
                     kind: TokenKind::EqualEqual,
                     ..
                 }) => Op::EqualEqual,
+                Some(Token {
+                    kind: TokenKind::BangEqualEqual,
+                    ..
+                }) => Op::BangEqualEqual,
+                Some(Token {
+                    kind: TokenKind::EqualEqualEqual,
+                    ..
+                }) => Op::EqualEqualEqual,
                 Some(Token {
                     kind: TokenKind::LessEqual,
                     ..
@@ -579,6 +1233,14 @@ impl<'de> Parser<'de> {
                     kind: TokenKind::Slash,
                     ..
                 }) => Op::Slash,
+                Some(Token {
+                    kind: TokenKind::Percent,
+                    ..
+                }) => Op::Percent,
+                Some(Token {
+                    kind: TokenKind::TildeSlash,
+                    ..
+                }) => Op::TildeSlash,
                 Some(Token {
                     kind: TokenKind::And,
                     ..
@@ -587,6 +1249,10 @@ impl<'de> Parser<'de> {
                     kind: TokenKind::Or,
                     ..
                 }) => Op::Or,
+                Some(Token {
+                    kind: TokenKind::Equal,
+                    ..
+                }) => Op::Assign,
 
                 Some(token) => return Err(miette::miette! {
                     labels = vec![
@@ -595,7 +1261,7 @@ impl<'de> Parser<'de> {
                     help = format!("Unexpected {token:?}"),
                     "Expected an infix operator",
                 }
-                .with_source_code(self.whole.to_string())),
+                .with_source_code(self.lexer.source())),
             };
 
             if let Some((l_bp, ())) = postfix_binding_power(op) {
@@ -620,21 +1286,36 @@ impl<'de> Parser<'de> {
                 if l_bp < min_bp {
                     break;
                 }
-                self.lexer.next();
+                let op_token = self
+                    .lexer
+                    .next()
+                    .expect("checked Some above")
+                    .expect("handled Err above");
 
-                lhs = match op {
-                    // TODO: ternary
-                    // let mhs = self.parse_within(0);
-                    // assert_eq!(lexer.next(), Token::Op(':'));
-                    // let rhs = self.parse_within(r_bp);
-                    // TokenTree::Cons(op, vec![lhs, mhs, rhs])
-                    _ => {
-                        let rhs = self
-                            .parse_expression_within(r_bp)
-                            .wrap_err_with(|| format!("on the right-hand side of {lhs} {op}"))?;
-                        TokenTree::Cons(op, vec![lhs, rhs])
-                    }
-                };
+                if op == Op::Assign {
+                    self.check_assignment_target(&lhs, start, op_token.offset)?;
+                }
+
+                if op.is_comparison()
+                    && matches!(&lhs, TokenTree::Cons(prev, _) if prev.is_comparison())
+                {
+                    self.warn(Warning::ChainedComparison {
+                        src: self.lexer.source(),
+                        err_span: SourceSpan::from(
+                            op_token.offset..op_token.offset + op_token.origin.len(),
+                        ),
+                    });
+                }
+
+                // TODO: ternary
+                // let mhs = self.parse_within(0);
+                // assert_eq!(lexer.next(), Token::Op(':'));
+                // let rhs = self.parse_within(r_bp);
+                // TokenTree::Cons(op, vec![lhs, mhs, rhs])
+                let rhs = self
+                    .parse_expression_within(r_bp)
+                    .wrap_err_with(|| format!("on the right-hand side of {lhs} {op}"))?;
+                lhs = TokenTree::Cons(op, vec![lhs, rhs]);
                 continue;
             }
 
@@ -645,7 +1326,53 @@ impl<'de> Parser<'de> {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// A whole program whose statements are known to parse, along with its source.
+///
+/// ```
+/// use rusty::Program;
+///
+/// let program: Program = "print 1;".parse().unwrap();
+/// assert_eq!(program.statements()[0].to_string(), "(print 1.0)");
+/// assert!("print 1".parse::<Program>().is_err());
+/// ```
+pub struct Program {
+    source: String,
+}
+
+impl Program {
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The program's statements, parsed again from its source each time.
+    pub fn statements(&self) -> Vec<TokenTree<'_>> {
+        Parser::new(&self.source)
+            .parse()
+            .expect("program was already parsed successfully")
+    }
+}
+
+impl FromStr for Program {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parser::new(s).parse()?;
+        Ok(Self {
+            source: s.to_string(),
+        })
+    }
+}
+
+impl TryFrom<&str> for Program {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value")]
 pub enum Atom<'de> {
     String(Cow<'de, str>),
     Number(f64),
@@ -662,14 +1389,8 @@ impl fmt::Display for Atom<'_> {
             // NOTE: this feels more correct
             // Atom::String(s) => write!(f, "\"{s}\""),
             Atom::String(s) => write!(f, "{s}"),
-            Atom::Number(n) => {
-                if *n == n.trunc() {
-                    // tests require that integers are printed as N.0
-                    write!(f, "{n}.0")
-                } else {
-                    write!(f, "{n}")
-                }
-            }
+            // tests require that integers are printed as N.0
+            Atom::Number(n) => crate::lex::fmt_number(f, *n),
             Atom::Nil => write!(f, "nil"),
             Atom::Bool(b) => write!(f, "{b:?}"),
             Atom::Ident(i) => write!(f, "{i}"),
@@ -679,32 +1400,171 @@ impl fmt::Display for Atom<'_> {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub enum Op {
     Minus,
     Plus,
     Star,
+    StarStar,
     BangEqual,
+    BangEqualEqual,
     EqualEqual,
+    EqualEqualEqual,
     LessEqual,
     GreaterEqual,
     Less,
     Greater,
     Slash,
+    Percent,
+    TildeSlash,
     Bang,
     And,
     Or,
     Call,
+    Block,
+    Break,
+    Continue,
     For,
     Class,
+    Get,
+    Import,
+    Mod,
+    Use,
     Print,
     Return,
     Field,
+    Assign,
     Var,
     While,
     Group,
 }
 
+impl Op {
+    fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            Op::Less | Op::LessEqual | Op::Greater | Op::GreaterEqual
+        )
+    }
+}
+
+/// An operator that joins two operands, like `+` or `=`.
+///
+/// Unlike [`Op`], which also names statements and groupings, it can only hold a valid infix
+/// operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    FloorDivide,
+    Power,
+    Equal,
+    NotEqual,
+    StrictEqual,
+    StrictNotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    Assign,
+    Field,
+}
+
+/// An operator that applies to a single operand after it, `-` or `!`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+/// The binary operator a token stands for, like [`BinaryOp::Assign`] for `=`.
+///
+/// Any other token, like `(`, is handed back as the error.
+impl TryFrom<TokenKind> for BinaryOp {
+    type Error = TokenKind;
+
+    fn try_from(kind: TokenKind) -> Result<Self, Self::Error> {
+        let op = match kind {
+            TokenKind::Plus => BinaryOp::Add,
+            TokenKind::Minus => BinaryOp::Subtract,
+            TokenKind::Star => BinaryOp::Multiply,
+            TokenKind::Slash => BinaryOp::Divide,
+            TokenKind::Percent => BinaryOp::Remainder,
+            TokenKind::TildeSlash => BinaryOp::FloorDivide,
+            TokenKind::StarStar => BinaryOp::Power,
+            TokenKind::EqualEqual => BinaryOp::Equal,
+            TokenKind::BangEqual => BinaryOp::NotEqual,
+            TokenKind::EqualEqualEqual => BinaryOp::StrictEqual,
+            TokenKind::BangEqualEqual => BinaryOp::StrictNotEqual,
+            TokenKind::Less => BinaryOp::Less,
+            TokenKind::LessEqual => BinaryOp::LessEqual,
+            TokenKind::Greater => BinaryOp::Greater,
+            TokenKind::GreaterEqual => BinaryOp::GreaterEqual,
+            TokenKind::And => BinaryOp::And,
+            TokenKind::Or => BinaryOp::Or,
+            TokenKind::Equal => BinaryOp::Assign,
+            TokenKind::Dot => BinaryOp::Field,
+            kind => return Err(kind),
+        };
+        Ok(op)
+    }
+}
+
+/// The unary operator a token stands for, like [`UnaryOp::Negate`] for `-`.
+///
+/// Any other token is handed back as the error.
+impl TryFrom<TokenKind> for UnaryOp {
+    type Error = TokenKind;
+
+    fn try_from(kind: TokenKind) -> Result<Self, Self::Error> {
+        match kind {
+            TokenKind::Minus => Ok(UnaryOp::Negate),
+            TokenKind::Bang => Ok(UnaryOp::Not),
+            kind => Err(kind),
+        }
+    }
+}
+
+impl From<BinaryOp> for Op {
+    fn from(op: BinaryOp) -> Self {
+        match op {
+            BinaryOp::Add => Op::Plus,
+            BinaryOp::Subtract => Op::Minus,
+            BinaryOp::Multiply => Op::Star,
+            BinaryOp::Divide => Op::Slash,
+            BinaryOp::Remainder => Op::Percent,
+            BinaryOp::FloorDivide => Op::TildeSlash,
+            BinaryOp::Power => Op::StarStar,
+            BinaryOp::Equal => Op::EqualEqual,
+            BinaryOp::NotEqual => Op::BangEqual,
+            BinaryOp::StrictEqual => Op::EqualEqualEqual,
+            BinaryOp::StrictNotEqual => Op::BangEqualEqual,
+            BinaryOp::Less => Op::Less,
+            BinaryOp::LessEqual => Op::LessEqual,
+            BinaryOp::Greater => Op::Greater,
+            BinaryOp::GreaterEqual => Op::GreaterEqual,
+            BinaryOp::And => Op::And,
+            BinaryOp::Or => Op::Or,
+            BinaryOp::Assign => Op::Assign,
+            BinaryOp::Field => Op::Field,
+        }
+    }
+}
+
+impl From<UnaryOp> for Op {
+    fn from(op: UnaryOp) -> Self {
+        match op {
+            UnaryOp::Negate => Op::Minus,
+            UnaryOp::Not => Op::Bang,
+        }
+    }
+}
+
 impl fmt::Display for Op {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -714,21 +1574,34 @@ impl fmt::Display for Op {
                 Op::Minus => "-",
                 Op::Plus => "+",
                 Op::Star => "*",
+                Op::StarStar => "**",
                 Op::BangEqual => "!=",
+                Op::BangEqualEqual => "!==",
                 Op::EqualEqual => "==",
+                Op::EqualEqualEqual => "===",
                 Op::LessEqual => "<=",
                 Op::GreaterEqual => ">=",
                 Op::Less => "<",
                 Op::Greater => ">",
                 Op::Slash => "/",
+                Op::Percent => "%",
+                Op::TildeSlash => "~/",
                 Op::Bang => "!",
                 Op::And => "and",
                 Op::Or => "or",
+                Op::Block => "block",
+                Op::Break => "break",
+                Op::Continue => "continue",
                 Op::For => "for",
                 Op::Class => "class",
+                Op::Get => "get",
+                Op::Import => "import",
+                Op::Mod => "mod",
+                Op::Use => "use",
                 Op::Print => "print",
                 Op::Return => "return",
                 Op::Field => ".",
+                Op::Assign => "=",
                 Op::Var => "var",
                 Op::While => "while",
                 Op::Call => "call",
@@ -738,15 +1611,38 @@ impl fmt::Display for Op {
     }
 }
 
+/// Where a leaf of a [`TokenTree`] sits in the source.
+///
+/// Always compares equal, so the same code laid out differently still parses to equal trees.
+#[derive(Debug, Clone, Copy)]
+pub struct LeafSpan(pub Span);
+
+impl PartialEq for LeafSpan {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl From<Span> for LeafSpan {
+    fn from(span: Span) -> Self {
+        Self(span)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenTree<'de> {
-    Atom(Atom<'de>),
+    Atom(Atom<'de>, LeafSpan),
     Cons(Op, Vec<TokenTree<'de>>),
     Fun {
         name: Atom<'de>,
         parameters: Vec<Token<'de>>,
         body: Box<TokenTree<'de>>,
     },
+    /// `fun (parameters) { body }` used as an expression.
+    Lambda {
+        parameters: Vec<Token<'de>>,
+        body: Box<TokenTree<'de>>,
+    },
     Call {
         callee: Box<TokenTree<'de>>,
         arguments: Vec<TokenTree<'de>>,
@@ -756,12 +1652,32 @@ pub enum TokenTree<'de> {
         yes: Box<TokenTree<'de>>,
         no: Option<Box<TokenTree<'de>>>,
     },
+    /// `{key: value, ...}`, with string or number keys.
+    Map(Vec<(TokenTree<'de>, TokenTree<'de>)>),
+    /// `assert condition;`, keeping the condition's source `text` for the failure message.
+    Assert {
+        condition: Box<TokenTree<'de>>,
+        text: &'de str,
+    },
+}
+
+impl TokenTree<'_> {
+    /// The file named by an `import "file";` statement.
+    pub fn import_path(&self) -> Option<&str> {
+        match self {
+            TokenTree::Cons(Op::Import, args) => match args.as_slice() {
+                [TokenTree::Atom(Atom::String(path), _)] => Some(path),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for TokenTree<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            TokenTree::Atom(i) => write!(f, "{}", i),
+            TokenTree::Atom(i, _) => write!(f, "{}", i),
             TokenTree::Cons(head, rest) => {
                 write!(f, "({}", head)?;
                 for s in rest {
@@ -780,6 +1696,13 @@ impl fmt::Display for TokenTree<'_> {
                 }
                 write!(f, " {body})")
             }
+            TokenTree::Lambda { parameters, body } => {
+                write!(f, "(lambda")?;
+                for p in parameters {
+                    write!(f, " {p}")?
+                }
+                write!(f, " {body})")
+            }
             TokenTree::Call { callee, arguments } => {
                 write!(f, "({callee}")?;
                 for a in arguments {
@@ -789,15 +1712,129 @@ impl fmt::Display for TokenTree<'_> {
             }
             TokenTree::If { condition, yes, no } => {
                 write!(f, "(if {condition} {yes}")?;
+                // `else if` leaves an `if` directly in the else branch, not wrapped in a block
                 if let Some(no) = no {
                     write!(f, " {no}")?
                 }
                 write!(f, ")")
             }
+            TokenTree::Map(entries) => {
+                write!(f, "(map")?;
+                for (key, value) in entries {
+                    write!(f, " ({key} {value})")?
+                }
+                write!(f, ")")
+            }
+            TokenTree::Assert { condition, .. } => write!(f, "(assert {condition})"),
+        }
+    }
+}
+
+/// Serializes each node as a map tagged with its `type`, e.g. `{"type":"Binary","op":"Plus",...}`.
+impl Serialize for TokenTree<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            TokenTree::Atom(atom, LeafSpan(span)) => {
+                #[derive(Serialize)]
+                struct Leaf<'a, 'de> {
+                    #[serde(flatten)]
+                    atom: &'a Atom<'de>,
+                    start: usize,
+                    end: usize,
+                }
+                Leaf {
+                    atom,
+                    start: span.start,
+                    end: span.end,
+                }
+                .serialize(serializer)
+            }
+            TokenTree::Cons(op, children) => {
+                let kind = match (op, children.len()) {
+                    (Op::Group, _) => "Group",
+                    (Op::Bang | Op::Minus, 1) => "Unary",
+                    (op, 2) if infix_binding_power(*op).is_some() => "Binary",
+                    _ => "Cons",
+                };
+                let mut map = serializer.serialize_map(Some(3))?;
+                map.serialize_entry("type", kind)?;
+                map.serialize_entry("op", op)?;
+                map.serialize_entry("children", children)?;
+                map.end()
+            }
+            TokenTree::Fun {
+                name,
+                parameters,
+                body,
+            } => {
+                let parameters: Vec<_> = parameters.iter().map(|p| p.origin).collect();
+                let mut map = serializer.serialize_map(Some(4))?;
+                map.serialize_entry("type", "Fun")?;
+                map.serialize_entry("name", name)?;
+                map.serialize_entry("parameters", &parameters)?;
+                map.serialize_entry("body", body)?;
+                map.end()
+            }
+            TokenTree::Lambda { parameters, body } => {
+                let parameters: Vec<_> = parameters.iter().map(|p| p.origin).collect();
+                let mut map = serializer.serialize_map(Some(3))?;
+                map.serialize_entry("type", "Lambda")?;
+                map.serialize_entry("parameters", &parameters)?;
+                map.serialize_entry("body", body)?;
+                map.end()
+            }
+            TokenTree::Call { callee, arguments } => {
+                let mut map = serializer.serialize_map(Some(3))?;
+                map.serialize_entry("type", "Call")?;
+                map.serialize_entry("callee", callee)?;
+                map.serialize_entry("arguments", arguments)?;
+                map.end()
+            }
+            TokenTree::If { condition, yes, no } => {
+                let mut map = serializer.serialize_map(Some(4))?;
+                map.serialize_entry("type", "If")?;
+                map.serialize_entry("condition", condition)?;
+                map.serialize_entry("yes", yes)?;
+                map.serialize_entry("no", no)?;
+                map.end()
+            }
+            TokenTree::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("type", "Map")?;
+                map.serialize_entry("entries", entries)?;
+                map.end()
+            }
+            TokenTree::Assert { condition, text } => {
+                let mut map = serializer.serialize_map(Some(3))?;
+                map.serialize_entry("type", "Assert")?;
+                map.serialize_entry("condition", condition)?;
+                map.serialize_entry("text", text)?;
+                map.end()
+            }
         }
     }
 }
 
+impl TokenKind {
+    /// The Pratt binding power of this token as a prefix operator, like the `-` in `-x`.
+    pub fn prefix_binding_power(self) -> Option<((), u8)> {
+        let op = match self {
+            TokenKind::Print => Op::Print,
+            TokenKind::Return => Op::Return,
+            kind => UnaryOp::try_from(kind).ok()?.into(),
+        };
+        Some(prefix_binding_power(op))
+    }
+
+    /// The left and right Pratt binding powers of this token as an infix operator.
+    ///
+    /// The side with the higher power binds tighter, so `(2, 1)` for `=` makes it
+    /// right-associative.
+    pub fn infix_binding_power(self) -> Option<(u8, u8)> {
+        infix_binding_power(BinaryOp::try_from(self).ok()?.into())
+    }
+}
+
 fn prefix_binding_power(op: Op) -> ((), u8) {
     match op {
         Op::Print | Op::Return => ((), 1),
@@ -816,19 +1853,514 @@ fn postfix_binding_power(op: Op) -> Option<(u8, ())> {
 
 fn infix_binding_power(op: Op) -> Option<(u8, u8)> {
     let res = match op {
-        // '=' => (2, 1),
+        Op::Assign => (2, 1),
         // '?' => (4, 3),
         Op::And | Op::Or => (3, 4),
         Op::BangEqual
+        | Op::BangEqualEqual
         | Op::EqualEqual
+        | Op::EqualEqualEqual
         | Op::Less
         | Op::LessEqual
         | Op::Greater
         | Op::GreaterEqual => (5, 6),
         Op::Plus | Op::Minus => (7, 8),
-        Op::Star | Op::Slash => (9, 10),
+        Op::Star | Op::Slash | Op::Percent | Op::TildeSlash => (9, 10),
+        // right-associative, and binds tighter than unary minus: -2 ** 2 == -(2 ** 2)
+        Op::StarStar => (12, 11),
         Op::Field => (16, 15),
         _ => return None,
     };
     Some(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::TokenKindTag;
+    use miette::SourceCode;
+
+    fn sexprs(src: &str) -> Vec<String> {
+        Parser::new(src)
+            .parse()
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn blocks_hold_terminated_statements() {
+        assert_eq!(
+            sexprs("while (x) { print 1; print 2; }"),
+            ["(while x (block (print 1.0) (print 2.0)))"]
+        );
+        assert_eq!(sexprs("while (x) {}"), ["(while x (block))"]);
+        assert!(Parser::new("while (x) { print 1 }").parse().is_err());
+        assert!(Parser::new("while (x) { print 1;").parse().is_err());
+    }
+
+    #[test]
+    fn program_keeps_its_statements() {
+        let program: Program = "var a = 1; print a;".parse().unwrap();
+        assert_eq!(program.source(), "var a = 1; print a;");
+        let statements: Vec<_> = program
+            .statements()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(statements, ["(var a 1.0)", "(print a)"]);
+    }
+
+    #[test]
+    fn break_and_continue_inside_loops() {
+        assert_eq!(
+            sexprs("while (x) { break; }"),
+            ["(while x (block (break)))"]
+        );
+        assert_eq!(
+            sexprs("while (x) { if (y) { continue; } }"),
+            ["(while x (block (if y (block (continue)))))"]
+        );
+    }
+
+    #[test]
+    fn break_and_continue_outside_loops() {
+        for src in [
+            "break;",
+            "continue;",
+            "if (x) { break; }",
+            "while (x) { fun f() { continue; } }",
+        ] {
+            let err = Parser::new(src).parse().unwrap_err();
+            let outside = err
+                .chain()
+                .find_map(|e| e.downcast_ref::<OutsideLoop>())
+                .unwrap_or_else(|| panic!("{src}: {err:?}"));
+            assert!(matches!(outside.keyword, Op::Break | Op::Continue));
+        }
+    }
+
+    #[test]
+    fn lambdas() {
+        assert_eq!(
+            sexprs("var add = fun (a, b) { return a + b; };"),
+            ["(var add (lambda IDENTIFIER a null IDENTIFIER b null (block (return (+ a b)))))"]
+        );
+        assert_eq!(sexprs("print fun () {};"), ["(print (lambda (block)))"]);
+        assert!(Parser::new("var f = fun (a { };").parse().is_err());
+    }
+
+    #[test]
+    fn modules_and_uses() {
+        assert_eq!(
+            sexprs("mod m { var x = 1; }"),
+            ["(mod m (block (var x 1.0)))"]
+        );
+        assert_eq!(
+            sexprs("mod m { mod n { fun f() {} } }"),
+            ["(mod m (block (mod n (block (def f (block))))))"]
+        );
+        assert_eq!(sexprs("use a::b::c;"), ["(use a b c)"]);
+        assert!(Parser::new("use a::;").parse().is_err());
+        assert!(Parser::new("mod { }").parse().is_err());
+    }
+
+    #[test]
+    fn getters_only_in_class_bodies() {
+        assert_eq!(
+            sexprs("class C { get area { return 1; } }"),
+            ["(class C (block (get area (block (return 1.0)))))"]
+        );
+        assert!(Parser::new("get area { return 1; }").parse().is_err());
+        assert!(Parser::new("class C { fun f() { get area { } } }")
+            .parse()
+            .is_err());
+        // `get` is still an ordinary name
+        assert_eq!(
+            sexprs("var get = 1; print get;"),
+            ["(var get 1.0)", "(print get)"]
+        );
+    }
+
+    #[test]
+    fn errors_carry_the_source_name() {
+        let err = Parser::new("var = 1;")
+            .with_source_name("prog.lox")
+            .parse()
+            .unwrap_err();
+        let unexpected = err
+            .chain()
+            .find_map(|e| e.downcast_ref::<UnexpectedToken>())
+            .unwrap();
+        let contents = unexpected
+            .src
+            .read_span(&unexpected.err_span, 0, 0)
+            .unwrap();
+        assert_eq!(contents.name(), Some("prog.lox"));
+    }
+
+    #[test]
+    fn validate_reports_every_statement() {
+        assert!(Parser::new("var x = 1; print x;").validate().is_empty());
+        // the `;` that fails the first statement must not swallow the second
+        assert_eq!(Parser::new("var x = ;\nvar y = ;").validate().len(), 2);
+        assert_eq!(Parser::new("print 1 +;\nprint ;").validate().len(), 2);
+        // recovery skips the rest of a statement that failed before its `;`
+        assert_eq!(Parser::new("print (1 2; print 3;").validate().len(), 1);
+    }
+
+    fn warnings(src: &str) -> usize {
+        let mut parser = Parser::new(src).with_warnings();
+        parser.parse().unwrap();
+        parser.warnings().len()
+    }
+
+    #[test]
+    fn chained_comparisons_warn() {
+        assert_eq!(warnings("print 1 < 2 < 3;"), 1);
+        assert_eq!(warnings("print 1 < 2 and 2 < 3;"), 0);
+        assert_eq!(warnings("print (1 < 2) == true;"), 0);
+        // without with_warnings, nothing is collected
+        let mut parser = Parser::new("print 1 < 2 < 3;");
+        parser.parse().unwrap();
+        assert!(parser.warnings().is_empty());
+    }
+
+    #[test]
+    fn records_consumed_tokens() {
+        let mut parser = Parser::new("1 + 2").with_token_recording();
+        parser.parse_expression().unwrap();
+        let kinds: Vec<_> = parser.tokens().map(|token| token.kind.tag()).collect();
+        assert_eq!(
+            kinds,
+            [
+                TokenKindTag::Number,
+                TokenKindTag::Plus,
+                TokenKindTag::Number
+            ]
+        );
+        assert_eq!(Parser::new("1 + 2").tokens().count(), 0);
+    }
+
+    fn expr(src: &str) -> String {
+        Parser::new(src).parse_expression().unwrap().to_string()
+    }
+
+    #[test]
+    fn power_is_right_associative_and_binds_tightly() {
+        assert_eq!(expr("2 ** 3 ** 2"), "(** 2.0 (** 3.0 2.0))");
+        assert_eq!(expr("2 * 3 ** 2"), "(* 2.0 (** 3.0 2.0))");
+        assert_eq!(expr("-2 ** 2"), "(- (** 2.0 2.0))");
+    }
+
+    #[test]
+    fn json_tags_node_types() {
+        let tree = Parser::new("1 + 2").parse_expression().unwrap();
+        assert_eq!(
+            serde_json::to_value(&tree).unwrap(),
+            serde_json::json!({
+                "type": "Binary",
+                "op": "Plus",
+                "children": [
+                    {"type": "Number", "value": 1.0, "start": 0, "end": 1},
+                    {"type": "Number", "value": 2.0, "start": 4, "end": 5},
+                ],
+            })
+        );
+
+        let tree = Parser::new("x = nil").parse_expression().unwrap();
+        assert_eq!(
+            serde_json::to_value(&tree).unwrap()["children"],
+            serde_json::json!([
+                {"type": "Ident", "value": "x", "start": 0, "end": 1},
+                {"type": "Nil", "start": 4, "end": 7},
+            ])
+        );
+    }
+
+    #[test]
+    fn modulo_and_floor_division_bind_like_multiplication() {
+        assert_eq!(expr("1 + 7 % 3"), "(+ 1.0 (% 7.0 3.0))");
+        assert_eq!(expr("7 ~/ 2 * 3"), "(* (~/ 7.0 2.0) 3.0)");
+    }
+
+    #[test]
+    fn literals_print_as_written() {
+        assert_eq!(expr("!nil"), "(! nil)");
+        assert_eq!(expr("true == false"), "(== true false)");
+    }
+
+    #[test]
+    fn grouping_is_kept() {
+        assert_eq!(expr("(1 + 2)"), "(group (+ 1.0 2.0))");
+        assert_eq!(expr("(1 + 2) * 3"), "(* (group (+ 1.0 2.0)) 3.0)");
+    }
+
+    fn invalid_target(src: &str) -> Option<SourceSpan> {
+        let err = Parser::new(src).parse_expression().unwrap_err();
+        let invalid = err
+            .chain()
+            .find_map(|e| e.downcast_ref::<InvalidAssignmentTarget>())?;
+        Some(invalid.err_span)
+    }
+
+    #[test]
+    fn assignment_targets() {
+        assert_eq!(sexprs("a = 1;"), ["(= a 1.0)"]);
+        assert_eq!(sexprs("a.b = 1;"), ["(= (. a b) 1.0)"]);
+        assert_eq!(invalid_target("1 = 2"), Some((0..1).into()));
+        assert_eq!(invalid_target("f() = 2"), Some((0..3).into()));
+    }
+
+    #[test]
+    fn validate_agrees_with_parse() {
+        for src in [
+            "var a = 1; print a;",
+            "while (a) { print 1; }",
+            "print 1 +;",
+            "var = 2;",
+        ] {
+            let parsed = Parser::new(src).parse();
+            let errors = Parser::new(src).validate();
+            match parsed {
+                Ok(_) => assert!(errors.is_empty(), "{src}"),
+                Err(e) => assert_eq!(format!("{:?}", errors[0]), format!("{e:?}"), "{src}"),
+            }
+        }
+    }
+
+    #[test]
+    fn binding_powers() {
+        let (_, plus) = TokenKind::Plus.infix_binding_power().unwrap();
+        let (star, _) = TokenKind::Star.infix_binding_power().unwrap();
+        assert!(star > plus);
+
+        let (left, right) = TokenKind::Equal.infix_binding_power().unwrap();
+        assert!(left > right, "= is right-associative");
+        let (left, right) = TokenKind::Minus.infix_binding_power().unwrap();
+        assert!(left < right, "- is left-associative");
+
+        assert!(TokenKind::Minus.prefix_binding_power().is_some());
+        assert_eq!(TokenKind::Star.prefix_binding_power(), None);
+        assert_eq!(TokenKind::LeftBrace.infix_binding_power(), None);
+    }
+
+    #[test]
+    fn unexpected_end_of_input() {
+        for src in ["1 +", "", "-", "1 + \n"] {
+            let err = Parser::new(src).parse_expression().unwrap_err();
+            let eof = err
+                .chain()
+                .find_map(|e| e.downcast_ref::<crate::lex::Eof>())
+                .unwrap_or_else(|| panic!("{src:?}: {err:?}"));
+            let end = src.trim_end().len();
+            let label = eof.labels().unwrap().next().unwrap();
+            assert_eq!(label.offset(), end, "{src:?}");
+        }
+
+        // an open bracket gets its own error, spanning the whole unclosed group
+        let err = Parser::new("(1 + 2").parse_expression().unwrap_err();
+        assert!(err.chain().any(|e| e.is::<UnclosedGroup>()));
+    }
+
+    #[test]
+    fn operators_from_token_kinds() {
+        assert_eq!(BinaryOp::try_from(TokenKind::Plus), Ok(BinaryOp::Add));
+        assert_eq!(BinaryOp::try_from(TokenKind::Equal), Ok(BinaryOp::Assign));
+        assert_eq!(
+            BinaryOp::try_from(TokenKind::LeftParen),
+            Err(TokenKind::LeftParen)
+        );
+        assert_eq!(UnaryOp::try_from(TokenKind::Minus), Ok(UnaryOp::Negate));
+        assert_eq!(UnaryOp::try_from(TokenKind::Star), Err(TokenKind::Star));
+        assert_eq!(Op::from(BinaryOp::Add), Op::Plus);
+        assert_eq!(Op::from(UnaryOp::Not), Op::Bang);
+    }
+
+    #[test]
+    fn else_if_chains_nest_in_the_else_branch() {
+        assert_eq!(
+            sexprs("if (a) { print 1; } else if (b) { print 2; } else if (c) {} else { print 3; }"),
+            ["(if a (block (print 1.0)) (if b (block (print 2.0)) (if c (block) (block (print 3.0)))))"]
+        );
+        // an `if` inside an else block is still nested
+        assert_eq!(
+            sexprs("if (a) {} else { if (b) {} }"),
+            ["(if a (block) (block (if b (block))))"]
+        );
+        let chain = Parser::new("if (a) {} else if (b) {}").parse().unwrap();
+        assert!(matches!(
+            &chain[0],
+            TokenTree::If { no: Some(no), .. } if matches!(**no, TokenTree::If { .. })
+        ));
+    }
+
+    #[test]
+    fn nesting_depth_limit() {
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        let parse = |src: &str| {
+            Parser::new(src)
+                .with_max_depth(10)
+                .parse_expression()
+                .map(|tree| tree.to_string())
+        };
+
+        // the outermost expression takes one level itself
+        assert!(parse(&nested(9)).is_ok());
+        let err = parse(&nested(10)).unwrap_err();
+        assert!(err.chain().any(|e| e.is::<TooDeeplyNested>()), "{err:?}");
+
+        // the default limit still turns very deep input into an error rather than a stack overflow
+        let err = Parser::new(&nested(100_000))
+            .parse_expression()
+            .unwrap_err();
+        assert!(err.chain().any(|e| e.is::<TooDeeplyNested>()));
+    }
+
+    #[test]
+    fn print_lists() {
+        assert_eq!(sexprs("print 1, 2, 3;"), ["(print 1.0 2.0 3.0)"]);
+        assert_eq!(sexprs("print 1;"), ["(print 1.0)"]);
+        assert_eq!(
+            sexprs("print (1), 2 + 3;"),
+            ["(print (group 1.0) (+ 2.0 3.0))"]
+        );
+        assert!(Parser::new("print 1,;").parse().is_err());
+    }
+
+    #[test]
+    fn unclosed_group_spans_to_the_end() {
+        let err = Parser::new("(1 + 2").parse_expression().unwrap_err();
+        let unclosed = err
+            .chain()
+            .find_map(|e| e.downcast_ref::<UnclosedGroup>())
+            .unwrap();
+        assert_eq!(unclosed.err_span, (0..6).into());
+
+        let err = Parser::new("print (1 + 2  \n").parse().unwrap_err();
+        let unclosed = err
+            .chain()
+            .find_map(|e| e.downcast_ref::<UnclosedGroup>())
+            .unwrap();
+        assert_eq!(unclosed.err_span, (6..12).into());
+    }
+
+    #[test]
+    fn repl_lines() {
+        let item = |src| Parser::new(src).parse_repl_line().unwrap();
+        assert!(matches!(item("1 + 1"), ReplItem::Expression(_)));
+        assert!(matches!(item("1 + 1;"), ReplItem::Expression(_)));
+        assert!(matches!(item("var x = 1;"), ReplItem::Statement(_)));
+        assert!(matches!(item("var x = 1"), ReplItem::Statement(_)));
+        assert!(matches!(item("print x;"), ReplItem::Statement(_)));
+    }
+
+    #[test]
+    fn assert_keeps_condition_text() {
+        let mut parser = Parser::new("assert a  ==  1 ;\nassert (b);");
+        let TokenTree::Assert { condition, text } = parser.parse_statement_within(0).unwrap()
+        else {
+            panic!("expected an assert statement");
+        };
+        assert_eq!(text, "a  ==  1");
+        assert_eq!(condition.to_string(), "(== a 1.0)");
+        assert_eq!(sexprs("assert (b);"), ["(assert (group b))"]);
+
+        let mut parser = Parser::new("assert x // note\n  ;");
+        let TokenTree::Assert { text, .. } = parser.parse_statement_within(0).unwrap() else {
+            panic!("expected an assert statement");
+        };
+        assert_eq!(text, "x");
+        assert!(Parser::new("assert ;").parse().is_err());
+    }
+
+    #[test]
+    fn map_literals() {
+        assert_eq!(
+            sexprs("var m = {\"a\": 1, 2: b + 1};"),
+            ["(var m (map (a 1.0) (2.0 (+ b 1.0))))"]
+        );
+        assert_eq!(sexprs("print {};"), ["(print (map))"]);
+        assert!(Parser::new("var m = {a: 1};").parse().is_err());
+        assert!(Parser::new("var m = {\"a\" 1};").parse().is_err());
+    }
+
+    #[test]
+    fn unexpected_token_names_both_kinds() {
+        let unexpected = |src: &str| {
+            let err = Parser::new(src).parse().unwrap_err();
+            let e = err
+                .chain()
+                .find_map(|e| e.downcast_ref::<UnexpectedToken>())
+                .expect("an UnexpectedToken");
+            (e.expected.tag(), e.found.tag(), e.line_col())
+        };
+        assert_eq!(
+            unexpected("while (x) {\n  print a\n}"),
+            (TokenKindTag::Semicolon, TokenKindTag::RightBrace, (3, 1))
+        );
+        assert_eq!(
+            unexpected("fun f() ) {}"),
+            (TokenKindTag::LeftBrace, TokenKindTag::RightParen, (1, 9))
+        );
+    }
+
+    #[test]
+    fn elif_means_else_if() {
+        let elif = |src: &str| {
+            let options = LexOptions {
+                elif_keyword: true,
+                ..LexOptions::default()
+            };
+            let statements = Parser::new(src).with_options(options).parse().unwrap();
+            statements[0].to_string()
+        };
+        assert_eq!(
+            elif("if (a) { print 1; } elif (b) { print 2; } else { print 3; }"),
+            sexprs("if (a) { print 1; } else if (b) { print 2; } else { print 3; }")[0]
+        );
+        assert_eq!(
+            elif("if (a) { print 1; } elif (b) { print 2; }"),
+            "(if a (block (print 1.0)) (if b (block (print 2.0))))"
+        );
+        // without the option `elif` is just a name
+        assert!(Parser::new("if (a) { print 1; } elif (b) { print 2; }")
+            .parse()
+            .is_err());
+    }
+
+    #[test]
+    fn equality_ignores_layout() {
+        let (tight, loose) = ("1+2", "1  +  2");
+        assert_eq!(
+            Parser::new(tight).parse_expression().unwrap(),
+            Parser::new(loose).parse_expression().unwrap()
+        );
+        // the operator tokens themselves sit at different places
+        let plus = |src| crate::lex::lex_all(src).unwrap()[1].span();
+        assert_ne!(plus(tight), plus(loose));
+
+        assert_ne!(
+            Parser::new("1+2").parse_expression().unwrap(),
+            Parser::new("1+3").parse_expression().unwrap()
+        );
+    }
+
+    #[test]
+    fn recovery_after_a_peeked_semicolon() {
+        // too deep to parse the returned value, which fails having only peeked at the `;`, so
+        // recovery still has to skip it rather than start the next statement there
+        let errors = Parser::new("return;\nreturn;").with_max_depth(1).validate();
+        assert_eq!(errors.len(), 2, "{errors:?}");
+    }
+
+    #[test]
+    fn peeked_tokens_are_not_recorded() {
+        // the expression stops at the `;` after peeking at it, without consuming it
+        let mut parser = Parser::new("a; b").with_token_recording();
+        parser.parse_expression().unwrap();
+        let kinds: Vec<_> = parser.tokens().map(|token| token.kind.tag()).collect();
+        assert_eq!(kinds, [TokenKindTag::Ident]);
+    }
+}
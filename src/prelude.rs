@@ -0,0 +1,20 @@
+//! The types most users of the crate need, for `use rusty::prelude::*`.
+//!
+//! ```
+//! use rusty::prelude::*;
+//!
+//! let token = Lexer::new("while").next().unwrap().unwrap();
+//! assert_eq!(token.kind, TokenKind::While);
+//! ```
+
+pub use crate::cst::{Cst, Node};
+pub use crate::lex::{
+    BadCharsError, Eof, LeadingZeroError, LexOptions, LexWarning, Lexer, LineDirectiveError,
+    Located, NumberLiteralError, OwnedToken, SingleTokenError, StringTerminationError, Token,
+    TokenKind, TokenKindTag,
+};
+pub use crate::parse::{
+    Atom, BinaryOp, InvalidAssignmentTarget, LeafSpan, Op, OutsideLoop, Parser, Program, ReplItem,
+    TokenTree, TooDeeplyNested, UnaryOp, UnclosedGroup, UnexpectedToken, Warning,
+};
+pub use crate::span::Span;
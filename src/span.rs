@@ -0,0 +1,164 @@
+use miette::SourceSpan;
+use std::ops::Range;
+
+/// A byte range into the source, from `start` up to but not including `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// The 1-based line and column of `start` in `src`, with columns counted in characters.
+    pub fn line_col(&self, src: &str) -> (usize, usize) {
+        let before = &src[..self.start];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line = before.matches('\n').count() + 1;
+        let col = before[line_start..].chars().count() + 1;
+        (line, col)
+    }
+
+    pub fn slice<'a>(&self, src: &'a str) -> &'a str {
+        &src[self.start..self.end]
+    }
+
+    /// The smallest span covering both `self` and `other`, and anything in between.
+    pub fn merge(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
+impl From<Range<usize>> for Span {
+    fn from(range: Range<usize>) -> Self {
+        Self::new(range.start, range.end)
+    }
+}
+
+impl From<Span> for Range<usize> {
+    fn from(span: Span) -> Self {
+        span.start..span.end
+    }
+}
+
+impl From<SourceSpan> for Span {
+    fn from(span: SourceSpan) -> Self {
+        Self::new(span.offset(), span.offset() + span.len())
+    }
+}
+
+impl From<Span> for SourceSpan {
+    fn from(span: Span) -> Self {
+        SourceSpan::from(span.start..span.end)
+    }
+}
+
+/// Line and column lookups for a run of increasing offsets into one source, such as the spans of
+/// its tokens in order.
+///
+/// Each lookup only scans the text since the previous one, so a whole file takes one pass, where
+/// calling [`Span::line_col`] for every token would rescan the file from the start each time.
+pub struct LineCols<'a> {
+    src: &'a str,
+    offset: usize,
+    line: usize,
+    col: usize,
+}
+
+impl<'a> LineCols<'a> {
+    pub fn new(src: &'a str) -> Self {
+        Self {
+            src,
+            offset: 0,
+            line: 1,
+            col: 1,
+        }
+    }
+
+    /// The 1-based line and column of `offset`, as [`Span::line_col`] gives them.
+    ///
+    /// # Panics
+    ///
+    /// If `offset` is before the offset of the previous lookup.
+    pub fn line_col(&mut self, offset: usize) -> (usize, usize) {
+        assert!(offset >= self.offset, "offsets must not go backwards");
+        for c in self.src[self.offset..offset].chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.col = 1;
+            } else {
+                self.col += 1;
+            }
+        }
+        self.offset = offset;
+        (self.line, self.col)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_col() {
+        let src = "var a;\n  print é + b;";
+        assert_eq!(Span::new(0, 3).line_col(src), (1, 1));
+        assert_eq!(Span::new(4, 5).line_col(src), (1, 5));
+        assert_eq!(Span::new(9, 14).line_col(src), (2, 3));
+        // columns count characters, so the two-byte `é` is one column
+        let plus = src.find('+').unwrap();
+        assert_eq!(Span::new(plus, plus + 1).line_col(src), (2, 11));
+    }
+
+    #[test]
+    fn slice() {
+        let src = "print 1 + 2;";
+        assert_eq!(Span::new(6, 11).slice(src), "1 + 2");
+        assert_eq!(Span::new(6, 6).slice(src), "");
+        assert!(Span::new(6, 6).is_empty());
+        assert_eq!(Span::new(6, 11).len(), 5);
+    }
+
+    #[test]
+    fn merge() {
+        let a = Span::new(2, 4);
+        let b = Span::new(7, 9);
+        assert_eq!(a.merge(b), Span::new(2, 9));
+        assert_eq!(b.merge(a), Span::new(2, 9));
+        assert_eq!(a.merge(Span::new(3, 3)), a);
+    }
+
+    #[test]
+    fn conversions() {
+        let span = Span::from(3..5);
+        assert_eq!(Range::from(span), 3..5);
+        assert_eq!(Span::from(SourceSpan::from(span)), span);
+    }
+
+    #[test]
+    fn line_cols_match_line_col() {
+        let src = "var a;\n  print é + b;\n\nx";
+        let mut line_cols = LineCols::new(src);
+        for offset in [0, 4, 4, 9, 17, 21, 23] {
+            assert_eq!(
+                line_cols.line_col(offset),
+                Span::new(offset, offset).line_col(src),
+                "at {offset}"
+            );
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! An experimental backend that compiles arithmetic-only programs to WebAssembly text.
+//!
+//! Every number is an `f64`, and `print` calls a `print` function imported from `env`, which the
+//! host supplies.
+use crate::parse::{Atom, Op, TokenTree};
+use miette::Diagnostic;
+use std::fmt::Write;
+use thiserror::Error;
+
+#[derive(Diagnostic, Debug, Error)]
+#[error("Can't compile {what} to WebAssembly yet")]
+#[diagnostic(help("only `print` statements and number arithmetic with + - * / are supported"))]
+pub struct Unsupported {
+    pub what: String,
+}
+
+/// Compiles `statements` to a module exporting a `main` function that runs them.
+pub fn emit(statements: &[TokenTree<'_>]) -> Result<String, Unsupported> {
+    let mut body = String::new();
+    for statement in statements {
+        match statement {
+            TokenTree::Cons(Op::Print, args) => {
+                for arg in args {
+                    emit_expression(arg, &mut body)?;
+                    body.push_str("    call $print\n");
+                }
+            }
+            // an expression statement, whose value is thrown away
+            statement => {
+                emit_expression(statement, &mut body)?;
+                body.push_str("    drop\n");
+            }
+        }
+    }
+
+    Ok(format!(
+        "(module\n  (import \"env\" \"print\" (func $print (param f64)))\n  (func (export \"main\")\n{body}  )\n)\n"
+    ))
+}
+
+/// Appends instructions that leave the value of `expression` on the stack.
+fn emit_expression(expression: &TokenTree<'_>, out: &mut String) -> Result<(), Unsupported> {
+    match expression {
+        TokenTree::Atom(Atom::Number(n), _) => {
+            let n = if n.is_nan() {
+                "nan".to_string()
+            } else if n.is_infinite() {
+                format!("{}inf", if *n < 0.0 { "-" } else { "" })
+            } else {
+                n.to_string()
+            };
+            writeln!(out, "    f64.const {n}").expect("writing to a String never fails");
+        }
+        TokenTree::Cons(Op::Group, args) if args.len() == 1 => emit_expression(&args[0], out)?,
+        TokenTree::Cons(Op::Minus, args) if args.len() == 1 => {
+            emit_expression(&args[0], out)?;
+            out.push_str("    f64.neg\n");
+        }
+        TokenTree::Cons(op @ (Op::Plus | Op::Minus | Op::Star | Op::Slash), args)
+            if args.len() == 2 =>
+        {
+            emit_expression(&args[0], out)?;
+            emit_expression(&args[1], out)?;
+            let instruction = match op {
+                Op::Plus => "f64.add",
+                Op::Minus => "f64.sub",
+                Op::Star => "f64.mul",
+                _ => "f64.div",
+            };
+            writeln!(out, "    {instruction}").expect("writing to a String never fails");
+        }
+        TokenTree::Atom(Atom::String(s), _) => {
+            return Err(Unsupported {
+                what: format!("the string \"{s}\""),
+            })
+        }
+        expression => {
+            return Err(Unsupported {
+                what: format!("`{expression}`"),
+            })
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Parser;
+
+    fn compile(src: &str) -> Result<String, Unsupported> {
+        emit(&Parser::new(src).parse().unwrap())
+    }
+
+    #[test]
+    fn arithmetic_and_print() {
+        let wat = compile("print 1 + 2;").unwrap();
+        assert!(wat.contains("    f64.const 1\n    f64.const 2\n    f64.add\n    call $print\n"));
+        assert!(wat.contains("(export \"main\")"), "{wat}");
+
+        let wat = compile("print -(3 * 4) / 2;").unwrap();
+        assert!(
+            wat.contains("f64.mul\n    f64.neg\n    f64.const 2\n    f64.div\n    call $print\n")
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        let err = compile("print \"hi\";").unwrap_err();
+        assert_eq!(err.what, "the string \"hi\"");
+        assert!(compile("var a = 1;").is_err());
+    }
+}
@@ -0,0 +1,34 @@
+//! `check` reports every bad statement in a file, not just the first, and warnings without
+//! failing.
+mod common;
+
+use common::{rusty, stderr, Scratch};
+
+#[test]
+fn reports_every_parse_error() {
+    let scratch = Scratch::new("check-errors");
+    let path = scratch.write("a.lox", "print 1 +;\nprint ;\nprint 2;");
+
+    let output = rusty(["check", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(65));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("print 1 +;"), "{stderr}");
+    assert!(stderr.contains("print ;"), "{stderr}");
+    assert_eq!(
+        stderr.matches("Expected an expression").count(),
+        2,
+        "{stderr}"
+    );
+}
+
+#[test]
+fn warnings_do_not_fail() {
+    let scratch = Scratch::new("check-warnings");
+    let path = scratch.write("a.lox", "print 1 < 2 < 3; // TODO: fix");
+
+    let output = rusty(["check", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("TODO comment"), "{stderr}");
+    assert!(stderr.contains("Chained comparison"), "{stderr}");
+}
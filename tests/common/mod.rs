@@ -0,0 +1,65 @@
+//! Helpers for the integration tests that run the `rusty` binary.
+// each test crate includes this module but only uses some of it
+#![allow(dead_code)]
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::{env, fs};
+
+/// Runs `rusty` with `args`, with colors turned off in its diagnostics.
+pub fn rusty<I, S>(args: I) -> Output
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    Command::new(env!("CARGO_BIN_EXE_rusty"))
+        .args(args)
+        .env("NO_COLOR", "1")
+        .output()
+        .expect("running rusty")
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+/// A fresh directory for one test's files, removed again when it is dropped.
+pub struct Scratch {
+    dir: PathBuf,
+}
+
+impl Scratch {
+    /// Creates the directory, named after `test`, which must be unique within the test crate.
+    pub fn new(test: &str) -> Self {
+        let dir = env::temp_dir().join(format!("rusty-{}-{test}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("creating scratch directory");
+        Self { dir }
+    }
+
+    /// Writes `contents` to the file `name` in the directory, returning its path.
+    pub fn write(&self, name: &str, contents: &str) -> PathBuf {
+        let path = self.path(name);
+        fs::write(&path, contents).expect("writing scratch file");
+        path
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
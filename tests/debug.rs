@@ -0,0 +1,26 @@
+//! The `debug` subcommand prints one line per lexing step.
+mod common;
+
+use common::{rusty, stdout, Scratch};
+
+#[test]
+fn steps() {
+    let scratch = Scratch::new("debug-steps");
+    let path = scratch.write("a.lox", "1+2");
+    let output = rusty(["debug", path.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    let stdout = stdout(&output);
+    let steps: Vec<(usize, &str)> = stdout
+        .lines()
+        .map(|line| {
+            let (byte, rest) = line.trim_start().split_once(' ').unwrap();
+            let (_, token) = rest.split_once(" -> ").unwrap();
+            (byte.parse().unwrap(), token)
+        })
+        .collect();
+    assert_eq!(
+        steps,
+        [(0, "NUMBER 1 1.0"), (1, "PLUS + null"), (2, "NUMBER 2 2.0")]
+    );
+}
@@ -0,0 +1,11 @@
+//! `-e` runs its argument as a program, printing each parsed statement.
+mod common;
+
+use common::{rusty, stdout};
+
+#[test]
+fn prints_each_statement() {
+    let output = rusty(["-e", "print 3*3; var a = -1;"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "(print (* 3.0 3.0))\n(var a (- 1.0))\n");
+}
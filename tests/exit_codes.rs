@@ -0,0 +1,66 @@
+//! Lexing and parsing errors exit with 65, from `sysexits.h`, on every path through the CLI.
+mod common;
+
+use common::Scratch;
+
+fn rusty(args: &[&str]) -> Option<i32> {
+    common::rusty(args).status.code()
+}
+
+#[test]
+fn eval() {
+    assert_eq!(rusty(&["-e", "print 3;"]), Some(0));
+    assert_eq!(rusty(&["-e", "print 3*;"]), Some(65));
+    assert_eq!(rusty(&["-e", "print \"oops;"]), Some(65));
+}
+
+#[test]
+fn subcommands() {
+    let scratch = Scratch::new("exit-codes-subcommands");
+    let good = scratch.write("good.lox", "print 1 + 2;");
+    let bad = scratch.write("bad.lox", "print 1 +;\n$");
+
+    for command in ["tokenize", "run", "check"] {
+        assert_eq!(
+            rusty(&[command, good.to_str().unwrap()]),
+            Some(0),
+            "{command} good.lox"
+        );
+        assert_eq!(
+            rusty(&[command, bad.to_str().unwrap()]),
+            Some(65),
+            "{command} bad.lox"
+        );
+    }
+
+    // `parse` reads a single expression
+    let expression = scratch.write("expression.lox", "1 + 2");
+    assert_eq!(rusty(&["parse", expression.to_str().unwrap()]), Some(0));
+    assert_eq!(rusty(&["parse", bad.to_str().unwrap()]), Some(65));
+}
+
+#[test]
+fn malformed_literals_and_directives() {
+    let scratch = Scratch::new("exit-codes-malformed");
+    for (name, src) in [("hex", "0xZ"), ("binary", "0b102"), ("directive", "#lin 3")] {
+        let path = scratch.write(&format!("{name}.lox"), src);
+        assert_eq!(
+            rusty(&["tokenize", path.to_str().unwrap()]),
+            Some(65),
+            "{src}"
+        );
+    }
+}
+
+#[test]
+fn import_cycle() {
+    let scratch = Scratch::new("exit-codes-import-cycle");
+    let a = scratch.write("a.lox", "import \"b.lox\";");
+    scratch.write("b.lox", "import \"a.lox\";");
+    assert_eq!(rusty(&["run", a.to_str().unwrap()]), Some(65));
+}
+
+#[test]
+fn missing_file_is_not_a_data_error() {
+    assert_eq!(rusty(&["run", "no/such/file.lox"]), Some(1));
+}
@@ -0,0 +1,41 @@
+//! Compares `tokenize` output for each `tests/fixtures/tokens/*.lox` against the reference format
+//! in the matching `.expected` file.
+//!
+//! A fixture with lexing errors also has an `.errors` file holding the `[line N] Error: ...` lines
+//! printed to stderr, and must exit with 65.
+mod common;
+
+use common::{rusty, stderr, stdout};
+use std::path::Path;
+use std::{fs, io};
+
+#[test]
+fn tokenize_fixtures() -> io::Result<()> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tokens");
+    let mut fixtures: Vec<_> = fs::read_dir(&dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    fixtures.retain(|path| path.extension().is_some_and(|ext| ext == "lox"));
+    fixtures.sort();
+    assert!(!fixtures.is_empty(), "no fixtures in {}", dir.display());
+
+    for fixture in fixtures {
+        let output = rusty(["tokenize", fixture.to_str().unwrap()]);
+        let name = fixture.display();
+
+        let expected = fs::read_to_string(fixture.with_extension("expected"))?;
+        assert_eq!(stdout(&output), expected, "{name}");
+
+        let errors = fs::read_to_string(fixture.with_extension("errors")).unwrap_or_default();
+        let reported: String = stderr(&output)
+            .lines()
+            .filter(|line| line.starts_with("[line "))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        assert_eq!(reported, errors, "{name}");
+
+        let code = if errors.is_empty() { 0 } else { 65 };
+        assert_eq!(output.status.code(), Some(code), "{name}");
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+//! `run` splices imported files in place of their `import` statements.
+mod common;
+
+use common::{rusty, stderr, stdout, Scratch};
+use std::path::Path;
+use std::process::Output;
+
+fn run(path: &Path) -> Output {
+    rusty(["run", path.to_str().unwrap()])
+}
+
+#[test]
+fn two_files() {
+    let scratch = Scratch::new("imports-two-files");
+    scratch.write("main.lox", "import \"lib.lox\";\nprint x;");
+    scratch.write("lib.lox", "var x = 1;");
+
+    let output = run(&scratch.path("main.lox"));
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "(var x 1.0)\n(print x)\n");
+}
+
+#[test]
+fn each_file_is_spliced_once() {
+    let scratch = Scratch::new("imports-once");
+    scratch.write("main.lox", "import \"lib.lox\";\nimport \"lib.lox\";");
+    scratch.write("lib.lox", "var x = 1;");
+
+    let output = run(&scratch.path("main.lox"));
+    assert_eq!(stdout(&output), "(var x 1.0)\n");
+}
+
+#[test]
+fn cycle() {
+    let scratch = Scratch::new("imports-cycle");
+    scratch.write("a.lox", "import \"b.lox\";");
+    scratch.write("b.lox", "import \"a.lox\";");
+
+    let output = run(&scratch.path("a.lox"));
+    assert_eq!(output.status.code(), Some(65));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("import cycle"), "{stderr}");
+}
+
+#[test]
+fn error_in_an_imported_file() {
+    let scratch = Scratch::new("imports-error");
+    scratch.write("main.lox", "print 1;\nimport \"lib.lox\";");
+    scratch.write("lib.lox", "var x = 1;\nprint x +;");
+
+    let output = run(&scratch.path("main.lox"));
+    assert_eq!(output.status.code(), Some(65));
+    // nothing runs if any file fails to parse
+    assert_eq!(stdout(&output), "");
+    let stderr = stderr(&output);
+    assert!(stderr.contains("imported from"), "{stderr}");
+    assert!(stderr.contains("lib.lox:2:"), "{stderr}");
+}
@@ -0,0 +1,27 @@
+//! Snapshots of the token stream for each `tests/fixtures/tokens/*.lox`, so changes to the lexer's
+//! output show up as reviewable diffs; run `cargo insta review` to accept them.
+use rusty::Lexer;
+use std::{fs, path::Path};
+
+#[test]
+fn token_snapshots() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tokens");
+    let mut fixtures: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lox"))
+        .collect();
+    fixtures.sort();
+
+    for fixture in fixtures {
+        let src = fs::read_to_string(&fixture).unwrap();
+        let tokens: String = Lexer::new(&src)
+            .map(|token| match token {
+                Ok(token) => format!("{token}\n"),
+                Err(e) => format!("error: {e}\n"),
+            })
+            .collect();
+        let name = fixture.file_stem().unwrap().to_str().unwrap();
+        insta::assert_snapshot!(name, tokens);
+    }
+}
@@ -0,0 +1,138 @@
+//! The `tokenize` subcommand's output options.
+mod common;
+
+use common::{rusty, stderr, stdout, Scratch};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Output;
+
+/// Writes each of `files` to a fresh directory named after `test`, returning it with their paths.
+fn write_files(test: &str, files: &[(&str, &str)]) -> (Scratch, Vec<PathBuf>) {
+    let scratch = Scratch::new(&format!("tokenize-{test}"));
+    let paths = files
+        .iter()
+        .map(|(name, src)| scratch.write(name, src))
+        .collect();
+    (scratch, paths)
+}
+
+fn tokenize(args: &[&str], files: &[PathBuf]) -> Output {
+    let args = args.iter().map(OsStr::new);
+    rusty(
+        [OsStr::new("tokenize")]
+            .into_iter()
+            .chain(args)
+            .chain(files.iter().map(|f| f.as_os_str())),
+    )
+}
+
+#[test]
+fn stats() {
+    let (_scratch, files) = write_files("stats", &[("a.lox", "var a = 1;\nprint a;")]);
+    let output = tokenize(&["--stats"], &files);
+    assert!(output.status.success());
+    let stats = stderr(&output);
+    assert!(stats.contains("tokens: 8\n"), "{stats}");
+    assert!(stats.contains("bytes: 19\n"), "{stats}");
+    assert!(stats.contains("elapsed: "), "{stats}");
+    assert!(stats.contains("peak allocation: "), "{stats}");
+}
+
+#[test]
+fn only() {
+    let (_scratch, files) = write_files("only", &[("a.lox", "var a = b + 1;")]);
+    let output = tokenize(&["--only", "IDENTIFIER"], &files);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "IDENTIFIER a null\nIDENTIFIER b null\n");
+
+    let output = tokenize(&["--only", "NOPE"], &files);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn pretty() {
+    let (_scratch, files) = write_files("pretty", &[("a.lox", "var a = 1;\nprint a;")]);
+    let output = tokenize(&["--pretty"], &files);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "\
+KIND        LEXEME  LINE  SPAN
+VAR         var        1  0..3
+IDENTIFIER  a          1  4..5
+EQUAL       =          1  6..7
+NUMBER      1          1  8..9
+SEMICOLON   ;          1  9..10
+PRINT       print      2  11..16
+IDENTIFIER  a          2  17..18
+SEMICOLON   ;          2  18..19
+EOF                    2  19..19
+"
+    );
+}
+
+#[test]
+fn group_by_line() {
+    let (_scratch, files) = write_files("group-by-line", &[("a.lox", "var a = 1;\nprint a;")]);
+    let output = tokenize(&["--group-by-line"], &files);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "line 1: VAR IDENTIFIER EQUAL NUMBER SEMICOLON\nline 2: PRINT IDENTIFIER SEMICOLON EOF\n"
+    );
+}
+
+#[test]
+fn several_files() {
+    let (_scratch, files) = write_files("several", &[("a.lox", "print $;"), ("b.lox", "var b;")]);
+    let output = tokenize(&[], &files);
+    assert_eq!(output.status.code(), Some(65));
+    let out = stdout(&output);
+    assert!(
+        out.starts_with(&format!("==> {} <==\n", files[0].display())),
+        "{out}"
+    );
+    let b = out
+        .find(&format!("==> {} <==\n", files[1].display()))
+        .expect("b.lox is tokenized after a.lox fails");
+    assert!(out[b..].contains("VAR var null\n"), "{out}");
+    assert!(stderr(&output).contains("Unexpected character: $"));
+}
+
+#[test]
+fn no_eof() {
+    let (_scratch, files) = write_files("no-eof", &[("a.lox", "print;")]);
+    let output = tokenize(&[], &files);
+    assert_eq!(
+        stdout(&output),
+        "PRINT print null\nSEMICOLON ; null\nEOF  null\n"
+    );
+    let output = tokenize(&["--no-eof"], &files);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "PRINT print null\nSEMICOLON ; null\n");
+}
+
+#[test]
+fn source_map() {
+    let src = "var a = 1;\nprint a;";
+    let (_scratch, files) = write_files("source-map", &[("a.lox", src)]);
+    let map_path = files[0].with_file_name("map.json");
+    let output = tokenize(&["--source-map", map_path.to_str().unwrap()], &files);
+    assert!(output.status.success());
+
+    let map: serde_json::Value = serde_json::from_str(&fs::read_to_string(&map_path).unwrap())
+        .expect("the source map is JSON");
+    assert_eq!(map[0]["file"], files[0].display().to_string());
+    let entries: Vec<[usize; 4]> = serde_json::from_value(map[0]["tokens"].clone()).unwrap();
+    assert_eq!(entries[..2], [[0, 3, 1, 1], [4, 5, 1, 5]]);
+    assert_eq!(entries[5], [11, 16, 2, 1]);
+
+    // one entry per printed token, each covering that token's lexeme
+    let printed = stdout(&output);
+    assert_eq!(entries.len(), printed.lines().count());
+    for (line, [start, end, ..]) in printed.lines().zip(&entries) {
+        let lexeme = line.split(' ').nth(1).unwrap();
+        assert_eq!(&src[*start..*end], lexeme, "{line}");
+    }
+}